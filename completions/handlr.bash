@@ -0,0 +1,254 @@
+_handlr_mimes() {
+  COMPREPLY=($(compgen -W "$(handlr autocomplete -m)" -- "$1"))
+}
+
+_handlr_desktops() {
+  COMPREPLY=($(compgen -W "$(handlr autocomplete -d | cut -f1)" -- "$1"))
+}
+
+_handlr() {
+  local cur=${COMP_WORDS[COMP_CWORD]}
+  local prev=${COMP_WORDS[COMP_CWORD-1]}
+
+  # the subcommand, and how many of its positional arguments come before
+  # the word being completed
+  local i cmd arg=0
+  for ((i = 1; i < COMP_CWORD; i++)); do
+    case ${COMP_WORDS[i]} in
+      --config|--mimeapps|--color|--with|--action|--mimes|--path) ((i++)) ;;
+      -*) ;;
+      *)
+        if [[ -z $cmd ]]; then
+          cmd=${COMP_WORDS[i]}
+        else
+          ((arg++))
+        fi
+        ;;
+    esac
+  done
+
+  case $prev in
+    --config|--mimeapps|--path)
+      COMPREPLY=($(compgen -f -- "$cur"))
+      return
+      ;;
+    --color)
+      COMPREPLY=($(compgen -W "auto always never" -- "$cur"))
+      return
+      ;;
+    --with)
+      _handlr_desktops "$cur"
+      return
+      ;;
+    --action) return ;;
+    --mimes)
+      _handlr_mimes "$cur"
+      return
+      ;;
+  esac
+
+  if [[ -z $cmd ]]; then
+    if [[ $cur == -* ]]; then
+      COMPREPLY=($(compgen -W "--desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+    else
+      COMPREPLY=($(compgen -W "list open set set-all unset unset-all add block remove get which-handles launch mime clean fix diff export import validate edit check-config config completions" -- "$cur"))
+    fi
+    return
+  fi
+
+  case $cmd in
+    list)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "-a --all --raw --json --wide --color --desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      ;;
+    open)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--with --action --desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        *) COMPREPLY=($(compgen -f -- "$cur")) ;;
+      esac
+      ;;
+    set)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--mimes --by-glob --desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        0) _handlr_mimes "$cur" ;;
+        1) _handlr_desktops "$cur" ;;
+      esac
+      ;;
+    set-all)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        0) _handlr_desktops "$cur" ;;
+      esac
+      ;;
+    unset)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        0) _handlr_mimes "$cur" ;;
+      esac
+      ;;
+    unset-all)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        0) _handlr_desktops "$cur" ;;
+      esac
+      ;;
+    add)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--first --by-glob --desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        0) _handlr_mimes "$cur" ;;
+        1) _handlr_desktops "$cur" ;;
+      esac
+      ;;
+    block)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        0) _handlr_mimes "$cur" ;;
+        1) _handlr_desktops "$cur" ;;
+      esac
+      ;;
+    remove)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        0) _handlr_mimes "$cur" ;;
+        1) _handlr_desktops "$cur" ;;
+      esac
+      ;;
+    get)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--json --desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        0) _handlr_mimes "$cur" ;;
+      esac
+      ;;
+    which-handles)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "-a --all --desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        0) _handlr_desktops "$cur" ;;
+      esac
+      ;;
+    launch)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--action --desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        0) _handlr_mimes "$cur" ;;
+        *) COMPREPLY=($(compgen -f -- "$cur")) ;;
+      esac
+      ;;
+    mime)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--json --desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        *) COMPREPLY=($(compgen -f -- "$cur")) ;;
+      esac
+      ;;
+    clean)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      ;;
+    fix)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      ;;
+    diff)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--json --desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        0) COMPREPLY=($(compgen -f -- "$cur")) ;;
+      esac
+      ;;
+    export)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--path --desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      ;;
+    import)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--merge --desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        0) COMPREPLY=($(compgen -f -- "$cur")) ;;
+      esac
+      ;;
+    validate)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--json --desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      ;;
+    edit)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      ;;
+    check-config)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      ;;
+    config)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        0) COMPREPLY=($(compgen -W "dump edit" -- "$cur")) ;;
+      esac
+      ;;
+    completions)
+      if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "--desktop --dry-run -v --verbose -0 --null --config --mimeapps" -- "$cur"))
+        return
+      fi
+      case $arg in
+        0) COMPREPLY=($(compgen -W "bash zsh fish" -- "$cur")) ;;
+      esac
+      ;;
+  esac
+}
+
+complete -F _handlr handlr
@@ -1,29 +1,106 @@
-function __handlr_autocomplete
-  function subcommands
-    set -l handlr_commands 'get help launch list open set unset'
-    complete -f -c handlr -n "not __fish_seen_subcommand_from $handlr_commands" -a "get" -d "Show handler for mime"
-    complete -f -c handlr -n "not __fish_seen_subcommand_from $handlr_commands" -a "launch" -d "Launch given handler with path/args"
-    complete -f -c handlr -n "not __fish_seen_subcommand_from $handlr_commands" -a "list" -d "Show handlers (default applications)"
-    complete -f -c handlr -n "not __fish_seen_subcommand_from $handlr_commands" -a "open" -d "Open path/URL with default handler (like xdg-open)"
-    complete -f -c handlr -n "not __fish_seen_subcommand_from $handlr_commands" -a "set" -d "Set handler for extension (e.g. pdf) or mime type"
-    complete -f -c handlr -n "not __fish_seen_subcommand_from $handlr_commands" -a "unset" -d "Unset handler"
-  end
-
-  function _set_add
-    complete -f -c handlr -n '__fish_seen_subcommand_from set; __fish_prev_arg_in "set"' -a '(handlr autocomplete -m)'
-    complete -f -c handlr -n '__fish_seen_subcommand_from set; set -l last (commandline -pco)[-2]; [ "$last" = "set" ]' -a '(handlr autocomplete -d)'
+# the positional arguments so far, without flags and their values
+function __handlr_args
+    set -l words (commandline -opc)
+    set -e words[1]
+    while set -q words[1]
+        switch $words[1]
+            case --config --mimeapps --color --with --action --mimes --path
+                set -e words[1]
+            case '-*'
+            case '*'
+                echo $words[1]
+        end
+        set -e words[1]
+    end
+end
 
-    complete -f -c handlr -n '__fish_seen_subcommand_from add; __fish_prev_arg_in "add"' -a '(handlr autocomplete -m)'
-    complete -f -c handlr -n '__fish_seen_subcommand_from add; set -l last (commandline -pco)[-2]; [ "$last" = "add" ]' -a '(handlr autocomplete -d)'
-  end
+function __handlr_no_command
+    set -l args (__handlr_args)
+    not set -q args[1]
+end
 
-  subcommands
-  _set_add
-  complete -f -c handlr -n '__fish_seen_subcommand_from get' -a '(handlr autocomplete -m)'
-  complete -f -c handlr -n '__fish_seen_subcommand_from get' -l 'json'
-  complete -f -c handlr -n '__fish_seen_subcommand_from unset' -a '(handlr autocomplete -m)'
-  complete -f -c handlr -n '__fish_seen_subcommand_from launch; __fish_prev_arg_in launch' -a '(handlr autocomplete -m)'
+# whether the subcommand is one of the comma-separated `names`
+function __handlr_command -a names
+    set -l args (__handlr_args)
+    contains -- "$args[1]" (string split , -- $names)
+end
 
+# whether the argument being typed is the subcommand's positional argument
+# at `index`, or any after it with `multiple`
+function __handlr_arg -a names index multiple
+    __handlr_command $names; or return
+    set -l typed (math (count (__handlr_args)) - 1)
+    test $typed -eq $index; and return
+    test -n "$multiple"; and test $typed -gt $index
 end
 
-__handlr_autocomplete
+complete -c handlr -f
+complete -c handlr -n __handlr_no_command -a list -d 'List default apps and the associated handlers'
+complete -c handlr -n __handlr_no_command -a open -d 'Open paths/URLs with their default handlers'
+complete -c handlr -n __handlr_no_command -a set -d 'Set the default handler for mime/extension'
+complete -c handlr -n __handlr_no_command -a set-all -d 'Set a handler as the default for every mime its desktop file lists'
+complete -c handlr -n __handlr_no_command -a unset -d 'Unset the default handler for mime/extension'
+complete -c handlr -n __handlr_no_command -a unset-all -d 'Remove a handler from every mime it\'s a default for'
+complete -c handlr -n __handlr_no_command -a add -d 'Add a fallback handler for given mime/extension'
+complete -c handlr -n __handlr_no_command -a block -d 'Block a handler for given mime/extension, the inverse of `add`'
+complete -c handlr -n __handlr_no_command -a remove -d 'Mask a handler for given mime/extension, even if the system adds it'
+complete -c handlr -n __handlr_no_command -a get -d 'Get handler for this mime/extension'
+complete -c handlr -n __handlr_no_command -a which-handles -d 'List the mimes a handler is the default or a fallback for'
+complete -c handlr -n __handlr_no_command -a launch -d 'Launch the handler for a mime/extension with the given arguments'
+complete -c handlr -n __handlr_no_command -a mime -d 'Print the mime type detected for files/URLs'
+complete -c handlr -n __handlr_no_command -a clean -d 'Remove associations whose desktop file was uninstalled'
+complete -c handlr -n __handlr_no_command -a fix -d 'Fix the current MIME associations by merging and removing MIME aliases'
+complete -c handlr -n __handlr_no_command -a diff -d 'Show how the associations changed since a saved mimeapps.list'
+complete -c handlr -n __handlr_no_command -a export -d 'Write the handler each mime resolves to, including the system\'s associations, as a JSON object'
+complete -c handlr -n __handlr_no_command -a import -d 'Set the defaults from a document written by `export`'
+complete -c handlr -n __handlr_no_command -a validate -d 'Report invalid mimes and missing, Hidden or NoDisplay handlers in mimeapps.list, failing if there are any'
+complete -c handlr -n __handlr_no_command -a edit -d 'Edit a copy of mimeapps.list in $EDITOR, saving it only if it parses'
+complete -c handlr -n __handlr_no_command -a check-config -d 'Check that the config and mimeapps.list parse, without changing them'
+complete -c handlr -n __handlr_no_command -a config -d 'Inspect handlr\'s own configuration'
+complete -c handlr -n __handlr_no_command -a completions -d 'Print a completion script for the given shell'
+complete -c handlr -l desktop -d 'Save to the current desktop\'s mimeapps.list, like gnome-mimeapps.list, instead of the plain one'
+complete -c handlr -l dry-run -d 'Print the changes to mimeapps.list instead of saving them'
+complete -c handlr -s v -l verbose -d 'Warn about mistakes in the files read, and with `get`, also show the Exec, Terminal, Path, Actions and MimeType of its desktop file'
+complete -c handlr -s 0 -l null -d 'End each printed field with a NUL byte, for `xargs -0`'
+complete -c handlr -l config -d 'Read handlr\'s config from this file instead of ~/.config/handlr/handlr.toml, also settable with $HANDLR_CONFIG' -r -F
+complete -c handlr -l mimeapps -d 'Read and save associations in this file instead of ~/.config/mimeapps.list, also settable with $HANDLR_MIMEAPPS' -r -F
+complete -c handlr -n '__handlr_command list' -s a -l all
+complete -c handlr -n '__handlr_command list' -l raw -d 'Print mimeapps.list as handlr would save it, without saving'
+complete -c handlr -n '__handlr_command list' -l json -d 'Print a JSON object mapping mimes to their handlers'
+complete -c handlr -n '__handlr_command list' -l wide -d 'Show every handler, wrapping long lists instead of truncating'
+complete -c handlr -n '__handlr_command list' -l color -d 'Color the mimes: always, never, or only on a terminal without $NO_COLOR set' -r -a 'auto always never'
+complete -c handlr -n '__handlr_command open' -l with -d 'Open them all with this handler, leaving the defaults alone' -r -a '(handlr autocomplete -d)'
+complete -c handlr -n '__handlr_command open' -l action -d 'Run one of the handler\'s desktop actions, like `new-window`' -r
+complete -c handlr -n '__handlr_arg open 0 multiple' -F
+complete -c handlr -n '__handlr_command set' -l mimes -d 'Additional mimes/extensions to set the same handler for' -r -a '(handlr autocomplete -m)'
+complete -c handlr -n '__handlr_command set' -l by-glob -d 'Use the mime of files matching a glob like \'*.md\''
+complete -c handlr -n '__handlr_arg set 0' -a '(handlr autocomplete -m)'
+complete -c handlr -n '__handlr_arg set 1' -a '(handlr autocomplete -d)'
+complete -c handlr -n '__handlr_arg set-all 0' -a '(handlr autocomplete -d)'
+complete -c handlr -n '__handlr_arg unset 0' -a '(handlr autocomplete -m)'
+complete -c handlr -n '__handlr_arg unset-all 0' -a '(handlr autocomplete -d)'
+complete -c handlr -n '__handlr_command add' -l first -d 'Make it the default, keeping the existing handlers as fallbacks'
+complete -c handlr -n '__handlr_command add' -l by-glob -d 'Use the mime of files matching a glob like \'*.md\''
+complete -c handlr -n '__handlr_arg add 0' -a '(handlr autocomplete -m)'
+complete -c handlr -n '__handlr_arg add 1' -a '(handlr autocomplete -d)'
+complete -c handlr -n '__handlr_arg block 0' -a '(handlr autocomplete -m)'
+complete -c handlr -n '__handlr_arg block 1' -a '(handlr autocomplete -d)'
+complete -c handlr -n '__handlr_arg remove 0' -a '(handlr autocomplete -m)'
+complete -c handlr -n '__handlr_arg remove 1' -a '(handlr autocomplete -d)'
+complete -c handlr -n '__handlr_command get' -l json -d 'Print a JSON object instead of the desktop file name'
+complete -c handlr -n '__handlr_arg get 0' -a '(handlr autocomplete -m)'
+complete -c handlr -n '__handlr_command which-handles' -s a -l all -d 'Include added associations'
+complete -c handlr -n '__handlr_arg which-handles 0' -a '(handlr autocomplete -d)'
+complete -c handlr -n '__handlr_command launch' -l action -d 'Run one of the handler\'s desktop actions, like `new-window`' -r
+complete -c handlr -n '__handlr_arg launch 0' -a '(handlr autocomplete -m)'
+complete -c handlr -n '__handlr_arg launch 1 multiple' -F
+complete -c handlr -n '__handlr_command mime' -l json -d 'Print a JSON array instead of `path<TAB>mime<TAB>guess` lines, where the last field is `guess` for a missing file\'s name'
+complete -c handlr -n '__handlr_arg mime 0 multiple' -F
+complete -c handlr -n '__handlr_command diff' -l json
+complete -c handlr -n '__handlr_arg diff 0' -F
+complete -c handlr -n '__handlr_command export' -l path -d 'Write to this file instead of stdout' -r -F
+complete -c handlr -n '__handlr_command import' -l merge -d 'Keep the defaults of mimes the document doesn\'t mention'
+complete -c handlr -n '__handlr_arg import 0' -F
+complete -c handlr -n '__handlr_command validate' -l json
+complete -c handlr -n '__handlr_arg config 0' -a 'dump edit'
+complete -c handlr -n '__handlr_arg completions 0' -a 'bash zsh fish'
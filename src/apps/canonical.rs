@@ -1,27 +1,28 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 use mime::Mime;
-use once_cell::sync::Lazy;
-use xdg_mime::SharedMimeInfo;
+use once_cell::sync::{Lazy, OnceCell};
 
-use crate::common::Handler;
-use crate::Result;
+use crate::common::{
+    mime_db_present, read_mime_db_pairs, read_mime_db_types, Handler, UserPath,
+};
+use crate::config::CONFIG;
+use crate::utils;
+use crate::{Error, Result};
 
-use super::user::{MimeApps, APPS};
+use super::system::SystemApps;
+use super::user::{MimeApps, MimeChange, Section};
 
-fn unalias_mime(db: &SharedMimeInfo, mime: &Mime) -> Mime {
-    // unalias_mime_type() performs a linear scan over the list of aliases.
-    // It should use a hashmap.
-    if let Some(canonical) = db.unalias_mime_type(mime) {
-        return canonical;
-    } else {
-        return mime.clone();
-    }
+/// map[alias] canonical mime
+type Aliases = HashMap<Mime, Mime>;
+
+fn unalias_mime(aliases: &Aliases, mime: &Mime) -> Mime {
+    aliases.get(mime).unwrap_or(mime).clone()
 }
 
 fn unalias_mime_map<V>(
-    db: &SharedMimeInfo,
+    aliases: &Aliases,
     mime_map: HashMap<Mime, V>,
 ) -> HashMap<Mime, V> {
     use std::collections::hash_map::Entry;
@@ -65,12 +66,14 @@ fn unalias_mime_map<V>(
     for picking the alphabetically first alias if no canonical MIME type is present,
     without sorting the iterator.
 
-    ...except it's pointless because for each item in the HashMap,
-    we call unalias_mime(), which calls SharedMimeInfo::unalias_mime_type(),
+    This used to be pointless, because for each item in the HashMap,
+    unalias_mime() called SharedMimeInfo::unalias_mime_type(),
     which linearly searches through all 300-ish MIME aliases present on the system
     (performing a string comparison for each).
-    Running O(n) linear searches will probably dwarf the O(n log n) time
+    Running O(n) linear searches would dwarf the O(n log n) time
     taken to sort the HashMap iterator.
+    Now the aliases are read into a HashMap once (see ALIASES),
+    so each unalias_mime() call is a single lookup.
 
     How do you count the number of MIME aliases on a system?
     The easy approach is to count the lines in /usr/share/mime/aliases
@@ -80,7 +83,7 @@ fn unalias_mime_map<V>(
     I don't know why the numbers don't line up.
     */
     for (mime, v) in mime_map.into_iter() {
-        let canonical_mime = unalias_mime(db, &mime);
+        let canonical_mime = unalias_mime(aliases, &mime);
         if mime == canonical_mime {
             // canonical mime wins. overwrite and discard the old value.
             canonical_map.insert(canonical_mime.clone(), (canonical_mime, v));
@@ -117,49 +120,362 @@ fn unalias_mime_map<V>(
         .collect()
 }
 
-static DB: Lazy<Arc<SharedMimeInfo>> =
-    Lazy::new(|| Arc::new(SharedMimeInfo::new()));
+// SharedMimeInfo::unalias_mime_type() performs a linear scan over the list
+// of aliases, and doesn't expose the list to build a faster lookup from.
+static ALIASES: Lazy<Arc<Aliases>> = Lazy::new(|| {
+    if !mime_db_present() {
+        // unalias_mime() falls back to the identity without a database
+        eprintln!(
+            "warning: no shared MIME database found, MIME aliases won't be \
+             merged. Install shared-mime-info to fix this."
+        );
+    }
+    let mut aliases = Aliases::new();
+    for (alias, canonical) in read_mime_db_pairs("aliases") {
+        // earlier data directories take precedence
+        aliases.entry(alias).or_insert(canonical);
+    }
+    Arc::new(aliases)
+});
+
+// SharedMimeInfo::get_parents() only answers for MIME aliases,
+// so read the subclass relations ourselves.
+static PARENTS: Lazy<Arc<HashMap<Mime, Vec<Mime>>>> = Lazy::new(|| {
+    let mut parents = HashMap::<Mime, Vec<Mime>>::new();
+    for (mime, parent) in read_mime_db_pairs("subclasses") {
+        let entry = parents.entry(mime).or_default();
+        if !entry.contains(&parent) {
+            entry.push(parent);
+        }
+    }
+    Arc::new(parents)
+});
+
+/// Read once, since every unknown mime is compared against them
+static MIME_DB_TYPES: Lazy<Vec<Mime>> = Lazy::new(read_mime_db_types);
+
+/// Unalias every mime in `mimeapps`, merging the entries of aliases
+fn unalias_mimeapps(aliases: &Aliases, mimeapps: MimeApps) -> MimeApps {
+    let mut comments = HashMap::<_, Vec<_>>::new();
+    for ((section, mime), mut lines) in mimeapps.comments {
+        comments
+            .entry((section, unalias_mime(aliases, &mime)))
+            .or_default()
+            .append(&mut lines);
+    }
+
+    MimeApps {
+        added_associations: unalias_mime_map(
+            aliases,
+            mimeapps.added_associations,
+        ),
+        removed_associations: unalias_mime_map(
+            aliases,
+            mimeapps.removed_associations,
+        ),
+        default_apps: unalias_mime_map(aliases, mimeapps.default_apps),
+        layout: mimeapps.layout,
+        comments,
+        invalid_mimes: mimeapps.invalid_mimes,
+        comma_separated: mimeapps.comma_separated,
+        spellings: mimeapps.spellings,
+    }
+}
 
 #[derive(Default, Clone)]
 pub struct CanonicalMimeApps {
-    db: Arc<SharedMimeInfo>,
+    aliases: Arc<Aliases>,
+    parents: Arc<HashMap<Mime, Vec<Mime>>>,
+    /// Read on the first lookup that gets this far
+    system: Arc<OnceCell<SystemApps>>,
+    /// The file edits are made to and saved to
     mimeapps: MimeApps,
+    /// The other files in `MimeApps::paths`, more and less important
+    /// than `mimeapps`. Only used for lookups.
+    before: Arc<Vec<MimeApps>>,
+    after: Arc<Vec<MimeApps>>,
 }
 
 impl From<MimeApps> for CanonicalMimeApps {
     fn from(mimeapps: MimeApps) -> CanonicalMimeApps {
-        let db = Arc::clone(&DB);
-
-        let added_associations =
-            unalias_mime_map(&db, mimeapps.added_associations);
-        let removed_associations =
-            unalias_mime_map(&db, mimeapps.removed_associations);
-        let default_apps = unalias_mime_map(&db, mimeapps.default_apps);
-
+        let aliases = Arc::clone(&ALIASES);
         CanonicalMimeApps {
-            db,
-            mimeapps: MimeApps {
-                added_associations,
-                removed_associations,
-                default_apps,
-            },
+            mimeapps: unalias_mimeapps(&aliases, mimeapps),
+            aliases,
+            parents: Arc::clone(&PARENTS),
+            system: Arc::default(),
+            before: Arc::default(),
+            after: Arc::default(),
         }
     }
 }
 
 impl CanonicalMimeApps {
+    /// Read the mimeapps.list we save to, along with the others in
+    /// `MimeApps::paths` for lookups
+    pub fn read() -> Result<Self> {
+        let mut apps = Self::from(MimeApps::read()?);
+        let (before, after) = MimeApps::read_others()?;
+        let unalias = |files: Vec<MimeApps>| {
+            files
+                .into_iter()
+                .map(|f| unalias_mimeapps(&apps.aliases, f))
+                .collect::<Vec<_>>()
+        };
+        let (before, after) = (unalias(before), unalias(after));
+        apps.before = Arc::new(before);
+        apps.after = Arc::new(after);
+        Ok(apps)
+    }
+
     fn unalias(&self, mime: &Mime) -> Mime {
-        unalias_mime(&self.db, mime)
+        unalias_mime(&self.aliases, mime)
+    }
+
+    /// Every mimeapps.list, most important first
+    fn files(&self) -> impl Iterator<Item = &MimeApps> {
+        self.before
+            .iter()
+            .chain(std::iter::once(&self.mimeapps))
+            .chain(self.after.iter())
     }
 
-    pub fn add_handler(&mut self, mime: Mime, handler: Handler) {
-        self.mimeapps.add_handler(self.unalias(&mime), handler)
+    /// Whether any file's [Removed Associations] masks `handler`
+    fn is_removed(&self, mime: &Mime, handler: &Handler) -> bool {
+        self.files().any(|f| f.is_removed(mime, handler))
+    }
+
+    pub fn add_handler(&mut self, mime: Mime, handler: Handler, first: bool) {
+        self.mimeapps
+            .add_handler(self.unalias(&mime), handler, first)
     }
 
     pub fn set_handler(&mut self, mime: Mime, handler: Handler) {
         self.mimeapps.set_handler(self.unalias(&mime), handler)
     }
 
+    /// Make the handlers of an `export`ed document the defaults, replacing
+    /// every existing default unless `merge`. Entries with an invalid mime
+    /// or a handler that isn't installed are skipped and returned.
+    pub fn import(
+        &mut self,
+        handlers: BTreeMap<String, String>,
+        merge: bool,
+    ) -> Vec<(String, String, Error)> {
+        if !merge {
+            self.mimeapps.default_apps.clear();
+        }
+        let mut skipped = Vec::new();
+        for (mime, handler) in handlers {
+            let parsed =
+                || -> Result<_> { Ok((mime.parse()?, handler.parse()?)) };
+            match parsed() {
+                Ok((mime, handler)) => self.set_handler(mime, handler),
+                Err(e) => skipped.push((mime, handler, e)),
+            }
+        }
+        skipped
+    }
+
+    pub fn unset_handler_everywhere(&mut self, handler: &Handler) -> Vec<Mime> {
+        self.mimeapps.unset_handler_everywhere(handler)
+    }
+
+    pub fn clean(&mut self) -> Vec<(Section, Mime, Handler)> {
+        self.mimeapps.clean()
+    }
+
+    pub fn handled_by(
+        &self,
+        handler: &Handler,
+        all: bool,
+    ) -> Vec<(Section, Mime, usize)> {
+        self.mimeapps.handled_by(handler, all)
+    }
+
+    pub fn block_handler(&mut self, mime: Mime, handler: Handler) {
+        self.mimeapps.block_handler(self.unalias(&mime), handler)
+    }
+
+    pub fn remove_association(&mut self, mime: Mime, handler: Handler) {
+        self.mimeapps
+            .remove_association(self.unalias(&mime), handler)
+    }
+
+    /// The default handlers, falling back to added associations and then
+    /// the system's, minus any removed associations. All three are tried
+    /// for the mime itself before its parents and wildcards.
+    pub fn get_handlers(&self, mime: &Mime) -> Result<Vec<Handler>> {
+        let mime = self.unalias(mime);
+        let unmasked =
+            |m: &Mime, handlers: &mut dyn Iterator<Item = &Handler>| {
+                let handlers = handlers
+                    .filter(|h| !self.is_removed(m, h))
+                    .filter(|h| h.is_available())
+                    .cloned()
+                    .collect::<Vec<_>>();
+                Some(handlers).filter(|h| !h.is_empty())
+            };
+
+        let mut found = None;
+        for m in self.lookup_order(&mime)? {
+            // a file whose handlers are all uninstalled defers to the next
+            let listed = |section| {
+                self.files().find_map(|f| {
+                    let handlers = f.section(section).get(&m)?;
+                    match section {
+                        Section::Default => {
+                            let handlers = handlers
+                                .iter()
+                                .filter(|h| h.is_available())
+                                .cloned()
+                                .collect::<Vec<_>>();
+                            Some(handlers).filter(|h| !h.is_empty())
+                        }
+                        _ => unmasked(&m, &mut handlers.iter()),
+                    }
+                })
+            };
+            found = listed(Section::Default).or_else(|| listed(Section::Added));
+            if found.is_none() {
+                found = unmasked(&m, &mut self.system()?.get(&m).iter());
+            }
+            if found.is_some() {
+                break;
+            }
+        }
+        found.ok_or_else(|| {
+            Error::NotFound(mime.to_string(), self.suggest(&mime))
+        })
+    }
+
+    /// A known mime close to `mime` if it's not one itself, likely what
+    /// was meant when nothing handles it
+    fn suggest(&self, mime: &Mime) -> Option<String> {
+        let listed = self.mimes();
+        let system = self.system().ok().map(|system| &system.0);
+        // known mimes need no suggestion, which covers all of `effective`
+        if listed.contains(&mime)
+            || system.is_some_and(|system| system.contains_key(mime))
+            || MIME_DB_TYPES.contains(mime)
+        {
+            return None;
+        }
+        let known = MIME_DB_TYPES
+            .iter()
+            .chain(listed)
+            .chain(system.into_iter().flat_map(|system| system.keys()));
+        utils::closest(mime.essence_str(), known.map(Mime::essence_str))
+            .map(str::to_owned)
+    }
+
+    pub fn get_handler(&self, mime: &Mime) -> Result<Handler> {
+        Ok(self.get_handlers(mime)?.remove(0))
+    }
+
+    /// Like `get_handler`, but lets the user pick with the selector
+    /// when there are several handlers and it's enabled
+    pub fn select_handler(&self, mime: &Mime) -> Result<Handler> {
+        let handlers = self.get_handlers(mime)?;
+        if !CONFIG.enable_selector || handlers.len() == 1 {
+            return Ok(handlers[0].clone());
+        }
+
+        CONFIG.select_handler(handlers)
+    }
+
+    /// Open each path with the handler for its mime, or all of them `with`
+    /// the given one. Paths sharing a handler are passed to it together.
+    pub fn open(
+        &self,
+        paths: &[UserPath],
+        with: Option<&Handler>,
+        action: Option<&str>,
+    ) -> Result<()> {
+        let mut groups: Vec<(Handler, Vec<String>)> = Vec::new();
+        for path in paths {
+            let handler = match with {
+                Some(handler) => handler.clone(),
+                None => self.select_handler(&path.mime_type()?)?,
+            };
+            match groups.iter_mut().find(|(h, _)| *h == handler) {
+                Some((_, args)) => args.push(path.to_string()),
+                None => groups.push((handler, vec![path.to_string()])),
+            }
+        }
+
+        for (handler, args) in groups {
+            handler.launch(args, action)?;
+        }
+        Ok(())
+    }
+
+    fn system(&self) -> Result<&SystemApps> {
+        self.system.get_or_try_init(|| {
+            let mut system = SystemApps::default();
+            for (mime, handlers) in SystemApps::read()?.0 {
+                system.add(self.unalias(&mime), handlers);
+            }
+            Ok(system)
+        })
+    }
+
+    /// The keys whose handlers apply to `mime`, most specific first:
+    /// the mime itself, the types it subclasses, then wildcards
+    fn lookup_order(&self, mime: &Mime) -> Result<Vec<Mime>> {
+        let mut order = vec![mime.clone()];
+
+        // breadth-first, so closer ancestors win
+        let mut i = 0;
+        while let Some(current) = order.get(i) {
+            let mut parents =
+                self.parents.get(current).cloned().unwrap_or_default();
+            // every text type is implicitly a subclass of text/plain
+            if current.type_() == mime::TEXT {
+                parents.push(mime::TEXT_PLAIN);
+            }
+            for parent in parents {
+                let parent = self.unalias(&parent);
+                if !order.contains(&parent) {
+                    order.push(parent);
+                }
+            }
+            i += 1;
+        }
+
+        if mime.subtype() != mime::STAR {
+            order.push(format!("{}/*", mime.type_()).parse()?);
+        }
+        if mime.type_() != mime::STAR {
+            order.push(mime::STAR_STAR);
+        }
+        Ok(order)
+    }
+
+    pub fn show_handler(
+        &self,
+        mime: &Mime,
+        output_json: bool,
+        verbose: bool,
+    ) -> Result<()> {
+        let handler = self.get_handler(mime)?;
+        if output_json {
+            let mut output = serde_json::json!({
+                "mime": self.unalias(mime).essence_str(),
+                "handler": handler.to_string(),
+            });
+            if verbose {
+                output["info"] = serde_json::to_value(handler.info()?)?;
+            }
+            println!("{}", output);
+        } else if verbose {
+            println!("{}", handler.info()?);
+        } else {
+            utils::print_record(&[handler.to_string()]);
+        }
+        Ok(())
+    }
+
     pub fn remove_handler(&mut self, mime: &Mime) -> Result<()> {
         // I suppose that if adding audio/x-flac (alias) adds audio/flac (canonical) instead,
         // then removing audio/x-flac should remove audio/flac instead.
@@ -170,14 +486,440 @@ impl CanonicalMimeApps {
         self.mimeapps.remove_handler(&self.unalias(mime))
     }
 
+    /// The mimes any mimeapps.list associates
+    pub fn mimes(&self) -> Vec<&Mime> {
+        use itertools::Itertools;
+
+        self.files()
+            .flat_map(MimeApps::mimes)
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    /// The handler that wins for every mime mimeapps.list or the system
+    /// associates, after masking removed associations, as `export` writes it
+    pub fn effective(&self) -> Result<BTreeMap<String, String>> {
+        let mut mimes = self.mimes().into_iter().cloned().collect::<Vec<_>>();
+        mimes.extend(self.system()?.0.keys().cloned());
+
+        let mut resolved = BTreeMap::new();
+        for mime in mimes {
+            match self.get_handler(&mime) {
+                Ok(handler) => {
+                    resolved.insert(
+                        mime.essence_str().to_owned(),
+                        handler.to_string(),
+                    );
+                }
+                Err(Error::NotFound(..)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(resolved)
+    }
+
     pub fn save(&self) -> Result<()> {
         self.mimeapps.save()
     }
 
-    pub fn print(&self, detailed: bool) -> Result<()> {
-        self.mimeapps.print(detailed)
+    pub fn print(
+        &self,
+        detailed: bool,
+        json: bool,
+        wide: bool,
+        color: bool,
+    ) -> Result<()> {
+        self.mimeapps.print(detailed, json, wide, color)
+    }
+
+    pub fn diff(&self, old: &CanonicalMimeApps) -> Vec<MimeChange> {
+        self.mimeapps.diff(&old.mimeapps)
+    }
+
+    pub fn write_to<W: std::io::Write>(&self, writer: &mut W) -> Result<()> {
+        self.mimeapps.write_to(writer)
     }
 }
 
 pub static CANONICAL: Lazy<CanonicalMimeApps> =
-    Lazy::new(|| CanonicalMimeApps::from(APPS.clone()));
+    Lazy::new(|| CanonicalMimeApps::read().unwrap());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Resolve handlers against the desktop files in tests/applications,
+    // without the associations installed on this system
+    fn canonical(list: &str) -> Result<CanonicalMimeApps> {
+        std::env::set_var(
+            "XDG_DATA_HOME",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests"),
+        );
+        let mut apps = CanonicalMimeApps::from(list.parse::<MimeApps>()?);
+        apps.system = Arc::new(SystemApps::default().into());
+        Ok(apps)
+    }
+
+    #[test]
+    fn get_handler() -> Result<()> {
+        let apps = canonical(
+            "[Default Applications]\ntext/html=firefox.desktop;nvim.desktop;\n",
+        )?;
+
+        assert_eq!(
+            apps.get_handler(&"text/html".parse()?)?,
+            "firefox.desktop".parse()?
+        );
+        assert!(matches!(
+            apps.get_handler(&"text/plain".parse()?),
+            Err(Error::NotFound(..))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn desktop_lists() -> Result<()> {
+        let mut apps = canonical(
+            "[Default Applications]
+text/html=firefox.desktop;
+[Added Associations]
+text/plain=nvim.desktop;firefox.desktop;
+",
+        )?;
+        apps.before = Arc::new(vec![
+            "[Default Applications]\ntext/html=nvim.desktop;\n".parse()?,
+        ]);
+        apps.after = Arc::new(vec![
+            "[Removed Associations]\ntext/plain=nvim.desktop;\n".parse()?,
+        ]);
+        let firefox = "firefox.desktop".parse::<Handler>()?;
+
+        // the more important file wins, and removals apply from any file
+        assert_eq!(
+            apps.get_handler(&mime::TEXT_HTML)?,
+            "nvim.desktop".parse()?
+        );
+        assert_eq!(apps.get_handler(&mime::TEXT_PLAIN)?, firefox);
+        // only the file that's saved is edited
+        assert_eq!(apps.mimeapps.default_apps[&mime::TEXT_HTML], [firefox]);
+        Ok(())
+    }
+
+    #[test]
+    fn uninstalled_desktop_default() -> Result<()> {
+        let mut apps =
+            canonical("[Default Applications]\ntext/html=firefox.desktop;\n")?;
+        apps.before = Arc::new(vec![
+            "[Default Applications]\ntext/html=uninstalled.desktop;\n"
+                .parse()?,
+        ]);
+
+        // the desktop's list has nothing installed, so the next file's wins
+        assert_eq!(
+            apps.get_handler(&mime::TEXT_HTML)?,
+            "firefox.desktop".parse()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn suggest_mime() -> Result<()> {
+        let apps = canonical(
+            "[Added Associations]
+x-handlr/hidden=hidden.desktop;
+
+[Default Applications]
+x-handlr/testing=nvim.desktop;
+",
+        )?;
+
+        match apps.get_handler(&"x-handlr/testin".parse()?) {
+            Err(Error::NotFound(mime, suggestion)) => {
+                assert_eq!(mime, "x-handlr/testin");
+                assert_eq!(suggestion.as_deref(), Some("x-handlr/testing"));
+            }
+            other => panic!("expected NotFound, got {:?}", other),
+        }
+        // no suggestion for known types that nothing usable handles
+        assert!(matches!(
+            apps.get_handler(&"x-handlr/hidden".parse()?),
+            Err(Error::NotFound(_, None))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn hidden_entries() -> Result<()> {
+        let apps = canonical(
+            "[Default Applications]
+text/html=hidden.desktop;nvim.desktop;
+text/plain=nodisplay.desktop;firefox.desktop;
+",
+        )?;
+
+        assert!(matches!(
+            Handler::resolve("hidden.desktop".into()),
+            Err(Error::NotFound(..))
+        ));
+        assert_eq!(
+            apps.get_handler(&mime::TEXT_HTML)?,
+            "nvim.desktop".parse()?
+        );
+        // NoDisplay entries are still valid handlers, in the order listed
+        assert_eq!(
+            apps.get_handler(&mime::TEXT_PLAIN)?,
+            "nodisplay.desktop".parse()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn try_exec() -> Result<()> {
+        let apps = canonical(
+            "[Default Applications]
+text/html=tryexec.desktop;nvim.desktop;
+",
+        )?;
+
+        assert!(matches!(
+            Handler::resolve("tryexec.desktop".into()),
+            Err(Error::NotFound(..))
+        ));
+        assert_eq!(
+            apps.get_handler(&mime::TEXT_HTML)?,
+            "nvim.desktop".parse()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn system_fallback() -> Result<()> {
+        let mut apps = canonical(
+            "[Added Associations]
+text/markdown=firefox.desktop;
+
+[Removed Associations]
+image/png=firefox.desktop;
+
+[Default Applications]
+text/html=nvim.desktop;
+",
+        )?;
+        let firefox = "firefox.desktop".parse::<Handler>()?;
+        let nvim = "nvim.desktop".parse::<Handler>()?;
+
+        let mut system = SystemApps::default();
+        system.add(mime::TEXT_HTML, vec![firefox.clone()]);
+        system.add("text/markdown".parse()?, vec![nvim.clone()]);
+        system.add(mime::IMAGE_PNG, vec![firefox.clone(), nvim.clone()]);
+        system.add(
+            mime::IMAGE_GIF,
+            vec![
+                Handler::assume_valid("uninstalled.desktop".into()),
+                firefox.clone(),
+            ],
+        );
+        apps.system = Arc::new(system.into());
+
+        // user defaults, then added associations, then the system
+        assert_eq!(apps.get_handler(&mime::TEXT_HTML)?, nvim);
+        assert_eq!(apps.get_handler(&"text/markdown".parse()?)?, firefox);
+        // skipping what's no longer installed
+        assert_eq!(apps.get_handler(&mime::IMAGE_GIF)?, firefox);
+        // removed associations mask the system's
+        assert_eq!(apps.get_handler(&mime::IMAGE_PNG)?, nvim);
+        Ok(())
+    }
+
+    #[test]
+    fn exact_before_parent() -> Result<()> {
+        let mut apps =
+            canonical("[Default Applications]\ntext/plain=nvim.desktop;\n")?;
+        let firefox = "firefox.desktop".parse::<Handler>()?;
+        let mut system = SystemApps::default();
+        system.add(mime::TEXT_HTML, vec![firefox.clone()]);
+        apps.system = Arc::new(system.into());
+
+        // the system's association for text/html beats the text/plain default
+        assert_eq!(apps.get_handler(&mime::TEXT_HTML)?, firefox);
+        assert_eq!(
+            apps.get_handler(&"text/markdown".parse()?)?,
+            "nvim.desktop".parse()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn effective() -> Result<()> {
+        let mut apps = canonical(
+            "[Added Associations]
+text/markdown=firefox.desktop;
+
+[Removed Associations]
+image/png=firefox.desktop;
+image/gif=firefox.desktop;
+
+[Default Applications]
+text/html=nvim.desktop;
+",
+        )?;
+        let mut system = SystemApps::default();
+        system.add(mime::TEXT_HTML, vec!["firefox.desktop".parse()?]);
+        system.add(
+            mime::IMAGE_PNG,
+            vec!["firefox.desktop".parse()?, "nvim.desktop".parse()?],
+        );
+        system.add(mime::IMAGE_GIF, vec!["firefox.desktop".parse()?]);
+        apps.system = Arc::new(system.into());
+
+        let expected = [
+            ("image/png", "nvim.desktop"),
+            ("text/html", "nvim.desktop"),
+            ("text/markdown", "firefox.desktop"),
+        ];
+        assert_eq!(
+            apps.effective()?,
+            expected
+                .iter()
+                .map(|&(m, h)| (m.to_owned(), h.to_owned()))
+                .collect::<BTreeMap<_, _>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn import() -> Result<()> {
+        let list = "[Default Applications]
+text/html=firefox.desktop;
+text/plain=firefox.desktop;
+";
+        let doc = [
+            ("text/plain", "nvim.desktop"),
+            ("text/markdown", "uninstalled.desktop"),
+            ("not a mime", "nvim.desktop"),
+        ]
+        .iter()
+        .map(|&(m, h)| (m.to_owned(), h.to_owned()))
+        .collect::<BTreeMap<_, _>>();
+        let firefox = "firefox.desktop".parse::<Handler>()?;
+        let nvim = "nvim.desktop".parse::<Handler>()?;
+
+        let mut merged = canonical(list)?;
+        let skipped = merged.import(doc.clone(), true);
+        assert_eq!(
+            skipped
+                .iter()
+                .map(|(m, h, _)| (m.as_str(), h.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("not a mime", "nvim.desktop"),
+                ("text/markdown", "uninstalled.desktop")
+            ]
+        );
+        assert_eq!(merged.get_handler(&mime::TEXT_HTML)?, firefox);
+        assert_eq!(merged.get_handler(&mime::TEXT_PLAIN)?, nvim);
+
+        let mut replaced = canonical(list)?;
+        assert_eq!(replaced.import(doc, false).len(), 2);
+        assert_eq!(replaced.get_handler(&mime::TEXT_PLAIN)?, nvim);
+        // text/html now falls back on text/plain
+        assert_eq!(replaced.get_handler(&mime::TEXT_HTML)?, nvim);
+        assert_eq!(replaced.mimes(), [&mime::TEXT_PLAIN]);
+        Ok(())
+    }
+
+    #[test]
+    fn many_aliases() -> Result<()> {
+        let db = xdg_mime::SharedMimeInfo::new();
+        let aliases = ALIASES.keys().collect::<Vec<_>>();
+        assert!(aliases.len() > 100, "expected a shared MIME database");
+
+        let list = aliases
+            .iter()
+            .map(|alias| format!("{}=nvim.desktop;\n", alias))
+            .collect::<String>();
+        let apps = canonical(&format!("[Default Applications]\n{}", list))?;
+
+        // every alias collapses onto the same canonical mime as before
+        for alias in aliases {
+            let canonical = db.unalias_mime_type(alias).unwrap();
+            assert_eq!(apps.unalias(alias), canonical);
+            assert!(apps.mimeapps.default_apps.contains_key(&canonical));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn alias_table() -> Result<()> {
+        if !mime_db_present() {
+            return Ok(());
+        }
+        let flac = "audio/flac".parse::<Mime>()?;
+        assert_eq!(ALIASES.get(&"audio/x-flac".parse()?), Some(&flac));
+        // only aliases are keys, so canonical mimes are left alone
+        assert!(!ALIASES.contains_key(&flac));
+        assert!(!ALIASES.contains_key(&mime::TEXT_PLAIN));
+        Ok(())
+    }
+
+    #[test]
+    fn subclass() -> Result<()> {
+        let apps =
+            canonical("[Default Applications]\ntext/plain=nvim.desktop;\n")?;
+        let nvim = "nvim.desktop".parse::<Handler>()?;
+
+        assert_eq!(apps.get_handler(&"application/json".parse()?)?, nvim);
+        assert_eq!(apps.get_handler(&"text/x-csrc".parse()?)?, nvim);
+        assert!(apps.get_handler(&mime::IMAGE_PNG).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn subclass_cycle() -> Result<()> {
+        let mut apps =
+            canonical("[Default Applications]\ntext/html=firefox.desktop;\n")?;
+        let (a, b) = (
+            "application/x-a".parse::<Mime>()?,
+            "application/x-b".parse::<Mime>()?,
+        );
+        apps.parents = Arc::new(
+            vec![(a.clone(), vec![b.clone()]), (b, vec![a.clone()])]
+                .into_iter()
+                .collect(),
+        );
+
+        assert!(apps.get_handler(&a).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn wildcard() -> Result<()> {
+        let apps = canonical(
+            "[Default Applications]
+image/*=firefox.desktop;
+image/svg+xml=nvim.desktop;
+",
+        )?;
+        let firefox = "firefox.desktop".parse::<Handler>()?;
+
+        assert_eq!(apps.get_handler(&mime::IMAGE_PNG)?, firefox);
+        assert_eq!(apps.get_handler(&"image/*".parse()?)?, firefox);
+        // exact matches beat the wildcard
+        assert_eq!(
+            apps.get_handler(&mime::IMAGE_SVG)?,
+            "nvim.desktop".parse()?
+        );
+        assert!(apps.get_handler(&mime::TEXT_PLAIN).is_err());
+
+        let apps = canonical(
+            "[Default Applications]\n*/*=nvim.desktop;\nimage/*=firefox.desktop;\n",
+        )?;
+        assert_eq!(apps.get_handler(&mime::IMAGE_PNG)?, firefox);
+        assert_eq!(
+            apps.get_handler(&mime::TEXT_PLAIN)?,
+            "nvim.desktop".parse()?
+        );
+        Ok(())
+    }
+}
@@ -1,5 +1,9 @@
 mod canonical;
+mod system;
 mod user;
 
 pub use canonical::{CanonicalMimeApps, CANONICAL};
-pub use user::{MimeApps, Rule as MimeappsRule, APPS};
+pub use user::{
+    MimeApps, Rule as MimeappsRule, Section, DRY_RUN, MIMEAPPS_PATH, VERBOSE,
+    WRITE_DESKTOP,
+};
@@ -0,0 +1,94 @@
+use crate::common::Handler;
+use crate::Result;
+use mime::Mime;
+use std::collections::HashMap;
+
+/// Associations registered by installed desktop files, as collected by
+/// update-desktop-database into applications/mimeinfo.cache
+#[derive(Debug, Default, Clone)]
+pub struct SystemApps(pub(super) HashMap<Mime, Vec<Handler>>);
+
+impl SystemApps {
+    /// Read every mimeinfo.cache in the XDG data directories,
+    /// listing handlers from more important directories first
+    pub fn read() -> Result<Self> {
+        let mut apps = Self::default();
+        for path in xdg::BaseDirectories::new()?
+            .find_data_files("applications/mimeinfo.cache")
+        {
+            apps.add_cache(&std::fs::read_to_string(path)?);
+        }
+        Ok(apps)
+    }
+
+    fn add_cache(&mut self, raw: &str) {
+        for line in raw.lines() {
+            let (mime, handlers) = match line.split_once('=') {
+                Some(pair) => pair,
+                // [MIME Cache] header, comments and blank lines
+                None => continue,
+            };
+            let mime = match mime.trim().parse::<Mime>() {
+                Ok(mime) => mime,
+                Err(_) => continue,
+            };
+
+            // resolving every entry is slow, lookups skip those whose
+            // desktop file was since uninstalled instead
+            let handlers = handlers
+                .split(';')
+                .filter(|name| !name.is_empty())
+                .map(|name| Handler::assume_valid(name.into()));
+            self.add(mime, handlers);
+        }
+    }
+
+    pub fn add(
+        &mut self,
+        mime: Mime,
+        handlers: impl IntoIterator<Item = Handler>,
+    ) {
+        let existing = self.0.entry(mime).or_default();
+        for handler in handlers {
+            if !existing.contains(&handler) {
+                existing.push(handler);
+            }
+        }
+    }
+
+    pub fn get(&self, mime: &Mime) -> &[Handler] {
+        self.0.get(mime).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mimeinfo_cache() -> Result<()> {
+        std::env::set_var(
+            "XDG_DATA_HOME",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests"),
+        );
+        let mut apps = SystemApps::default();
+        apps.add_cache(
+            "[MIME Cache]
+text/html=firefox.desktop;uninstalled.desktop;
+text/plain=nvim.desktop;
+",
+        );
+        apps.add_cache("text/html=nvim.desktop;firefox.desktop;\n");
+
+        let firefox = "firefox.desktop".parse::<Handler>()?;
+        let nvim = "nvim.desktop".parse::<Handler>()?;
+        let uninstalled = Handler::assume_valid("uninstalled.desktop".into());
+        assert_eq!(
+            apps.get(&mime::TEXT_HTML),
+            [firefox, uninstalled, nvim.clone()]
+        );
+        assert_eq!(apps.get(&mime::TEXT_PLAIN), [nvim]);
+        assert!(apps.get(&mime::IMAGE_PNG).is_empty());
+        Ok(())
+    }
+}
@@ -2,16 +2,31 @@ use crate::common::atomic_save::{
     AtomicFile, AtomicSaveError, Durability, OverwriteBehavior,
 };
 use crate::common::Handler;
+use crate::config::CONFIG;
+use crate::utils;
 use crate::{Error, Result};
 use mime::Mime;
-use once_cell::sync::Lazy;
+use once_cell::sync::OnceCell;
 use pest::Parser;
+use serde::Serialize;
 use std::collections::{HashMap, VecDeque};
-use std::io::Read;
-use std::path::PathBuf;
+use std::fmt::Display;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-pub static APPS: Lazy<MimeApps> = Lazy::new(|| MimeApps::read().unwrap());
+/// Set by `--desktop`, to save to the current desktop's mimeapps.list
+pub static WRITE_DESKTOP: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--dry-run`, to print what `save` would change instead
+pub static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Set by `-v/--verbose`, to warn about mistakes handlr read around
+pub static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--mimeapps`, taking precedence over $HANDLR_MIMEAPPS
+pub static MIMEAPPS_PATH: OnceCell<PathBuf> = OnceCell::new();
 
 #[derive(Debug, Default, Clone, pest_derive::Parser)]
 #[grammar = "common/ini.pest"]
@@ -19,20 +34,302 @@ pub struct MimeApps {
     pub(super) added_associations: HashMap<Mime, VecDeque<Handler>>,
     pub(super) removed_associations: HashMap<Mime, VecDeque<Handler>>,
     pub(super) default_apps: HashMap<Mime, VecDeque<Handler>>,
+    /// Sections in the order they appeared in the file, to minimize diffs,
+    /// along with the comments and sections handlr doesn't interpret
+    pub(super) layout: Vec<Block>,
+    /// Comment lines directly above a mime's line
+    pub(super) comments: HashMap<(Section, Mime), Vec<String>>,
+    /// Keys that aren't valid mimes, dropped while parsing
+    pub(super) invalid_mimes: Vec<(Section, String)>,
+    /// Keys whose handlers were separated by ',' instead of ';'
+    pub(super) comma_separated: Vec<(Section, String)>,
+    /// Mimes as they were written, when that wasn't in lowercase,
+    /// so saving doesn't change them
+    pub(super) spellings: HashMap<(Section, Mime), String>,
+}
+
+/// A part of mimeapps.list, in file order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Block {
+    Section(Section),
+    /// A section handlr doesn't know, passed through verbatim
+    Unknown(Vec<String>),
+    /// Comment lines, kept above whatever followed them
+    Comments(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Section {
+    Added,
+    Removed,
+    Default,
+}
+
+impl Section {
+    /// The order used for sections that didn't appear in the file
+    const ALL: [Section; 3] =
+        [Section::Added, Section::Removed, Section::Default];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Section::Added => "Added Associations",
+            Section::Removed => "Removed Associations",
+            Section::Default => "Default Applications",
+        }
+    }
+
+    fn from_title(title: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|s| s.title() == title)
+    }
+}
+
+/// A mime whose handlers differ between two `MimeApps`
+#[derive(Debug, PartialEq, Serialize)]
+pub struct MimeChange {
+    pub section: &'static str,
+    pub mime: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+impl Display for MimeChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.before.is_empty(), self.after.is_empty()) {
+            (true, _) => write!(f, "+ {}={};", self.mime, self.after.join(";")),
+            (_, true) => {
+                write!(f, "- {}={};", self.mime, self.before.join(";"))
+            }
+            _ => write!(
+                f,
+                "~ {}: {}; -> {};",
+                self.mime,
+                self.before.join(";"),
+                self.after.join(";")
+            ),
+        }
+    }
+}
+
+/// Something wrong with a line of mimeapps.list, found by `validate`
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Problem {
+    pub section: &'static str,
+    pub mime: String,
+    pub handler: Option<String>,
+    pub issue: &'static str,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.handler {
+            Some(handler) => {
+                write!(f, "{}={}: {}", self.mime, handler, self.issue)
+            }
+            None => write!(f, "{}: {}", self.mime, self.issue),
+        }
+    }
 }
 
 impl MimeApps {
-    pub fn add_handler(&mut self, mime: Mime, handler: Handler) {
-        self.default_apps
-            .entry(mime)
-            .or_default()
-            .push_back(handler);
+    pub(super) fn section(
+        &self,
+        section: Section,
+    ) -> &HashMap<Mime, VecDeque<Handler>> {
+        match section {
+            Section::Added => &self.added_associations,
+            Section::Removed => &self.removed_associations,
+            Section::Default => &self.default_apps,
+        }
+    }
+
+    fn section_mut(
+        &mut self,
+        section: Section,
+    ) -> &mut HashMap<Mime, VecDeque<Handler>> {
+        match section {
+            Section::Added => &mut self.added_associations,
+            Section::Removed => &mut self.removed_associations,
+            Section::Default => &mut self.default_apps,
+        }
+    }
+
+    /// Append the handler as a fallback, or put it `first` to make it
+    /// the default, moving it if it was already listed
+    pub fn add_handler(&mut self, mime: Mime, handler: Handler, first: bool) {
+        let handlers = self.default_apps.entry(mime).or_default();
+        if first {
+            handlers.retain(|h| h != &handler);
+            handlers.push_front(handler);
+        } else if !handlers.contains(&handler) {
+            handlers.push_back(handler);
+        }
     }
 
     pub fn set_handler(&mut self, mime: Mime, handler: Handler) {
         self.default_apps.insert(mime, vec![handler].into());
     }
 
+    /// The inverse of `add_handler`: drops the handler from the default and
+    /// added lists for `mime`, and masks it under [Removed Associations]
+    /// so system-provided associations stop applying too.
+    pub fn block_handler(&mut self, mime: Mime, handler: Handler) {
+        for map in [&mut self.default_apps, &mut self.added_associations] {
+            if let Some(handlers) = map.get_mut(&mime) {
+                handlers.retain(|h| h != &handler);
+                if handlers.is_empty() {
+                    map.remove(&mime);
+                }
+            }
+        }
+
+        self.remove_association(mime, handler);
+    }
+
+    /// Record the handler under [Removed Associations], masking it
+    /// for `mime` even if the system associates them
+    pub fn remove_association(&mut self, mime: Mime, handler: Handler) {
+        let removed = self.removed_associations.entry(mime).or_default();
+        if !removed.contains(&handler) {
+            removed.push_back(handler);
+        }
+    }
+
+    /// Drop the handler from every default list, returning the mimes
+    /// it was removed from, sorted
+    pub fn unset_handler_everywhere(&mut self, handler: &Handler) -> Vec<Mime> {
+        let mut affected = Vec::new();
+        self.default_apps.retain(|mime, handlers| {
+            let len = handlers.len();
+            handlers.retain(|h| h != handler);
+            if handlers.len() != len {
+                affected.push(mime.clone());
+            }
+            !handlers.is_empty()
+        });
+        affected.sort_unstable_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        affected
+    }
+
+    /// Drop handlers whose desktop file is gone from every section,
+    /// returning what was removed
+    pub fn clean(&mut self) -> Vec<(Section, Mime, Handler)> {
+        let mut removed = Vec::new();
+        for section in Section::ALL {
+            let mut missing = Vec::new();
+            self.section_mut(section).retain(|mime, handlers| {
+                handlers.retain(|h| {
+                    let installed = h.is_installed();
+                    if !installed {
+                        missing.push((section, mime.clone(), h.clone()));
+                    }
+                    installed
+                });
+                !handlers.is_empty()
+            });
+            missing.sort_by(|a, b| a.1.as_ref().cmp(b.1.as_ref()));
+            removed.extend(missing);
+        }
+        removed
+    }
+
+    /// Where `handler` appears among the defaults, and the added
+    /// associations too if `all`, as (section, mime, position) sorted by mime
+    pub fn handled_by(
+        &self,
+        handler: &Handler,
+        all: bool,
+    ) -> Vec<(Section, Mime, usize)> {
+        let mut sections = vec![Section::Default];
+        if all {
+            sections.push(Section::Added);
+        }
+
+        let mut found = Vec::new();
+        for section in sections {
+            let mut handled = self
+                .section(section)
+                .iter()
+                .filter_map(|(mime, handlers)| {
+                    let position =
+                        handlers.iter().position(|h| h == handler)?;
+                    Some((section, mime.clone(), position))
+                })
+                .collect::<Vec<_>>();
+            handled.sort_by(|a, b| a.1.as_ref().cmp(b.1.as_ref()));
+            found.extend(handled);
+        }
+        found
+    }
+
+    /// Invalid mimes, handlers separated by ',' and handlers that are
+    /// missing, Hidden or NoDisplay,
+    /// grouped by section and sorted by mime. Handlers under
+    /// [Removed Associations] only mask others, so they're not checked.
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        for section in Section::ALL {
+            let mut found = self
+                .invalid_mimes
+                .iter()
+                .filter(|(s, _)| *s == section)
+                .map(|(_, mime)| (mime.clone(), None, "not a valid mime"))
+                .chain(
+                    self.comma_separated
+                        .iter()
+                        .filter(|(s, _)| *s == section)
+                        .map(|(_, mime)| {
+                            let issue = "handlers separated by ',' instead \
+                                         of ';', run `handlr fix`";
+                            (mime.clone(), None, issue)
+                        }),
+                )
+                .collect::<Vec<_>>();
+            if section != Section::Removed {
+                for (mime, handlers) in self.section(section) {
+                    for handler in handlers {
+                        let issue = match handler.get_entry() {
+                            Err(_) => "desktop file not found",
+                            Ok(entry) if entry.hidden => "Hidden=true",
+                            Ok(entry) if entry.no_display => "NoDisplay=true",
+                            Ok(_) => continue,
+                        };
+                        let handler = Some(handler.to_string());
+                        found.push((mime.to_string(), handler, issue));
+                    }
+                }
+            }
+            found.sort_by(|a, b| a.0.cmp(&b.0));
+            problems.extend(found.into_iter().map(|(mime, handler, issue)| {
+                Problem {
+                    section: section.title(),
+                    mime,
+                    handler,
+                    issue,
+                }
+            }));
+        }
+        problems
+    }
+
+    /// Mimes with default or added handlers, sorted
+    pub fn mimes(&self) -> Vec<&Mime> {
+        use itertools::Itertools;
+
+        self.default_apps
+            .keys()
+            .chain(self.added_associations.keys())
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    /// Whether [Removed Associations] masks `handler` for `mime`
+    pub fn is_removed(&self, mime: &Mime, handler: &Handler) -> bool {
+        self.removed_associations
+            .get(mime)
+            .is_some_and(|removed| removed.contains(handler))
+    }
+
     pub fn remove_handler(&mut self, mime: &Mime) -> Result<()> {
         if let Some(_removed) = self.default_apps.remove(mime) {
             self.save()?;
@@ -41,161 +338,1312 @@ impl MimeApps {
         Ok(())
     }
 
+    /// The mimeapps.list files to read, most important first:
+    /// `$desktop-mimeapps.list` for each of $XDG_CURRENT_DESKTOP,
+    /// then the plain mimeapps.list. A file picked with `--mimeapps` or
+    /// $HANDLR_MIMEAPPS is the only one read instead.
+    pub fn paths() -> Result<Vec<PathBuf>> {
+        if let Some(path) = MIMEAPPS_PATH.get() {
+            return Ok(vec![path.clone()]);
+        }
+        if let Some(path) =
+            std::env::var_os("HANDLR_MIMEAPPS").filter(|path| !path.is_empty())
+        {
+            return Ok(vec![path.into()]);
+        }
+
+        let config = xdg::BaseDirectories::new()?.get_config_home();
+        let desktops = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+
+        Ok(desktops
+            .split(':')
+            .filter(|desktop| !desktop.is_empty())
+            .map(|desktop| {
+                config.join(format!("{}-mimeapps.list", desktop.to_lowercase()))
+            })
+            .chain(std::iter::once(config.join("mimeapps.list")))
+            .collect())
+    }
+    /// The file to save to, the plain mimeapps.list unless `--desktop`
+    /// picked the current desktop's
     pub fn path() -> Result<PathBuf> {
-        let mut config = xdg::BaseDirectories::new()?.get_config_home();
-        config.push("mimeapps.list");
-        Ok(config)
+        let mut paths = Self::paths()?;
+        if WRITE_DESKTOP.load(Ordering::Relaxed) {
+            Ok(paths.swap_remove(0))
+        } else {
+            Ok(paths.pop().unwrap())
+        }
     }
+    /// Read the mimeapps.list we save to, creating it
     pub fn read() -> Result<Self> {
-        let raw_conf = {
-            let mut buf = String::new();
-            std::fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .read(true)
-                .open(Self::path()?)?
-                .read_to_string(&mut buf)?;
-            buf
+        let path = Self::path()?;
+        if DRY_RUN.load(Ordering::Relaxed) {
+            return match path.exists() {
+                true => Self::read_from(&path),
+                false => Ok(Self::default()),
+            };
+        }
+        let mut buf = String::new();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .open(&path)?
+            .read_to_string(&mut buf)?;
+        Self::parse_file(&path, &buf)
+    }
+    /// The other mimeapps.list files in `paths` that exist, split into
+    /// those more and less important than the one we save to. They're
+    /// only consulted for lookups, and never saved.
+    pub fn read_others() -> Result<(Vec<Self>, Vec<Self>)> {
+        let target = Self::path()?;
+        let (mut before, mut after) = (Vec::new(), Vec::new());
+        let mut seen_target = false;
+        for path in Self::paths()? {
+            if path == target {
+                seen_target = true;
+            } else if path.exists() {
+                let apps = Self::read_from(&path)?;
+                match seen_target {
+                    false => before.push(apps),
+                    true => after.push(apps),
+                }
+            }
+        }
+        Ok((before, after))
+    }
+    pub fn read_from(path: &Path) -> Result<Self> {
+        Self::parse_file(path, &std::fs::read_to_string(path)?)
+    }
+    /// Parse the contents of the file at `path`, warning with `--verbose`
+    /// about handler lists that were split on ','
+    fn parse_file(path: &Path, contents: &str) -> Result<Self> {
+        let apps = contents.parse::<Self>()?;
+        if VERBOSE.load(Ordering::Relaxed) {
+            for (section, mime) in &apps.comma_separated {
+                eprintln!(
+                    "warning: {}: handlers for {} in [{}] are separated by \
+                     ',' instead of ';', run `handlr fix`",
+                    path.display(),
+                    mime,
+                    section.title()
+                );
+            }
+        }
+        Ok(apps)
+    }
+    /// Check that the mimeapps.list at `path` parses, without creating it
+    pub fn check(path: &Path) -> Result<()> {
+        if path.exists() {
+            std::fs::read_to_string(path)?.parse::<Self>()?;
+        }
+        Ok(())
+    }
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if DRY_RUN.load(Ordering::Relaxed) {
+            let before = std::fs::read_to_string(&path).unwrap_or_default();
+            let changes = line_diff(&before, &self.to_string()?);
+            if changes.is_empty() {
+                println!("{}: no changes", path.display());
+            } else {
+                println!("--- {}", path.display());
+                println!("+++ {} (dry run)", path.display());
+                print!("{}", changes);
+            }
+            return Ok(());
+        }
+
+        let durability = if CONFIG.durable_writes {
+            Durability::SyncDir
+        } else {
+            Durability::DontSyncDir
         };
-        let file = Self::parse(Rule::file, &raw_conf)?.next().unwrap();
+        self.save_to(&path, durability)
+    }
+    /// Atomically replace `path`, also syncing its directory with
+    /// `Durability::SyncDir` so the rename survives a crash
+    fn save_to(&self, path: &Path, durability: Durability) -> Result<()> {
+        use std::io::BufWriter;
 
-        let mut current_section_name = "".to_string();
-        let mut conf = Self {
-            added_associations: HashMap::default(),
-            removed_associations: HashMap::default(),
-            default_apps: HashMap::default(),
+        let af = AtomicFile::new(
+            path,
+            OverwriteBehavior::AllowOverwrite,
+            durability,
+        );
+        af.write(|f| self.write_to(&mut BufWriter::new(f)))
+            .map_err(|e| match e {
+                AtomicSaveError::Internal(e) => Error::Io(e),
+                AtomicSaveError::User(e) => e,
+            })?;
+        Ok(())
+    }
+    /// `write_to` into a string, for previews and tests
+    pub fn to_string(&self) -> Result<String> {
+        let mut out = Vec::new();
+        self.write_to(&mut out)?;
+        Ok(String::from_utf8_lossy(&out).into_owned())
+    }
+    /// Serialize to mimeapps.list format, exactly as `save` would write it
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        use itertools::Itertools;
+
+        let missing = Section::ALL
+            .iter()
+            .filter(|&&s| !self.layout.contains(&Block::Section(s)))
+            .map(|&s| Block::Section(s))
+            .collect::<Vec<_>>();
+        // comments at the end of the file stay there
+        let (body, trailer) = match self.layout.split_last() {
+            Some((Block::Comments(lines), body)) => (body, &lines[..]),
+            _ => (&self.layout[..], &[][..]),
+        };
+        let blocks = body.iter().chain(&missing).filter(|block| {
+            // [Removed Associations] is only written when non-empty
+            **block != Block::Section(Section::Removed)
+                || !self.removed_associations.is_empty()
+        });
+
+        let write_lines = |writer: &mut W, lines: &[String]| {
+            lines
+                .iter()
+                .try_for_each(|line| writeln!(writer, "{}", line))
+        };
+
+        // blocks are separated by a blank line,
+        // but comments stay attached to what follows them
+        let mut separate = false;
+        for block in blocks {
+            if separate {
+                writer.write_all(b"\n")?;
+            }
+            separate = true;
+
+            let section = match block {
+                Block::Comments(lines) => {
+                    write_lines(writer, lines)?;
+                    separate = false;
+                    continue;
+                }
+                Block::Unknown(lines) => {
+                    write_lines(writer, lines)?;
+                    continue;
+                }
+                Block::Section(section) => *section,
+            };
+
+            writer.write_all(format!("[{}]\n", section.title()).as_ref())?;
+            let handlers = self.section(section);
+            // `mime=;` wouldn't parse again, so empty lists are left out
+            let lines = handlers.iter().filter(|(_, v)| !v.is_empty());
+            for (k, v) in lines.sorted() {
+                if let Some(lines) = self.comments.get(&(section, k.clone())) {
+                    write_lines(writer, lines)?;
+                }
+                let key = self
+                    .spellings
+                    .get(&(section, k.clone()))
+                    .map_or(k.essence_str(), |s| s);
+                writer.write_all(key.as_ref())?;
+                writer.write_all(b"=")?;
+                writer.write_all(v.iter().join(";").as_ref())?;
+                writer.write_all(b";\n")?;
+            }
+            // keep comments whose mime has since been removed
+            let orphans = self
+                .comments
+                .iter()
+                .filter(|((s, mime), _)| {
+                    *s == section
+                        && handlers.get(mime).is_none_or(VecDeque::is_empty)
+                })
+                .sorted_by_key(|((_, mime), _)| mime);
+            for (_, lines) in orphans {
+                write_lines(writer, lines)?;
+            }
+        }
+        write_lines(writer, trailer)?;
+
+        writer.flush()?;
+        Ok(())
+    }
+    /// Mimes whose handlers changed since `old`, grouped by section
+    pub fn diff(&self, old: &MimeApps) -> Vec<MimeChange> {
+        use itertools::Itertools;
+
+        let names = |handlers: Option<&VecDeque<Handler>>| {
+            handlers
+                .into_iter()
+                .flatten()
+                .map(|h| h.to_string())
+                .collect::<Vec<_>>()
         };
 
-        file.into_inner().for_each(|line| {
+        let mut changes = Vec::new();
+        for section in Section::ALL {
+            let (old, new) = (old.section(section), self.section(section));
+            for mime in old.keys().chain(new.keys()).unique().sorted() {
+                let (before, after) =
+                    (names(old.get(mime)), names(new.get(mime)));
+                if before != after {
+                    changes.push(MimeChange {
+                        section: section.title(),
+                        mime: mime.essence_str().to_owned(),
+                        before,
+                        after,
+                    });
+                }
+            }
+        }
+        changes
+    }
+    /// Print the associations as a table, or JSON or NUL-separated records.
+    /// `wide` lists every handler, wrapping them onto more lines instead of
+    /// truncating.
+    pub fn print(
+        &self,
+        detailed: bool,
+        json: bool,
+        wide: bool,
+        color: bool,
+    ) -> Result<()> {
+        use itertools::Itertools;
+        use std::collections::BTreeMap;
+
+        if json {
+            let to_map = |map: &HashMap<Mime, VecDeque<Handler>>| {
+                map.iter()
+                    .map(|(k, v)| {
+                        let handlers =
+                            v.iter().map(|h| h.to_string()).collect_vec();
+                        (k.essence_str().to_owned(), handlers)
+                    })
+                    .collect::<BTreeMap<_, _>>()
+            };
+            let output = if detailed {
+                serde_json::json!({
+                    Section::Default.title(): to_map(&self.default_apps),
+                    Section::Added.title(): to_map(&self.added_associations),
+                })
+            } else {
+                serde_json::json!(to_map(&self.default_apps))
+            };
+            println!("{}", output);
+            return Ok(());
+        }
+
+        if utils::NULL_SEPARATED.load(Ordering::Relaxed) {
+            // every handler, with a section field once there are several
+            let mut sections = vec![Section::Default];
+            if detailed {
+                sections.push(Section::Added);
+            }
+            for section in sections {
+                for (k, v) in self.section(section).iter().sorted() {
+                    let mut record = vec![k.to_string(), v.iter().join(";")];
+                    if detailed {
+                        record.insert(0, section.title().to_owned());
+                    }
+                    utils::print_record(&record);
+                }
+            }
+            return Ok(());
+        }
+
+        let mut table = ascii_table::AsciiTable::default();
+        let max = CONFIG.max_handlers_display;
+        let table_width = table.max_width;
+        let to_rows = |map: &HashMap<Mime, VecDeque<Handler>>| {
+            let mime_width = map.keys().map(|k| k.as_ref().len()).max();
+            // the borders and padding of a two-column table take 7 chars
+            let handlers_width = table_width
+                .saturating_sub(mime_width.unwrap_or_default() + 7)
+                .max(20);
+
+            let mut rows = Vec::new();
+            for (k, v) in map.iter().sorted() {
+                let mime = k.to_string();
+                if !wide {
+                    rows.push(vec![mime, join_truncated(v, max)]);
+                    continue;
+                }
+                let mut lines = wrap_handlers(v, handlers_width).into_iter();
+                rows.push(vec![mime, lines.next().unwrap_or_default()]);
+                rows.extend(lines.map(|line| vec![String::new(), line]));
+            }
+            rows
+        };
+        if wide {
+            // long mimes would otherwise still get cut off
+            table.max_width = usize::MAX;
+        }
+
+        // color only once the table is laid out, so the escapes don't
+        // count towards the column widths
+        let print = |rows| {
+            let formatted = table.format(rows);
+            match color {
+                true => print!("{}", color_mimes(&formatted)),
+                false => print!("{}", formatted),
+            }
+        };
+        if detailed {
+            println!("Default Apps");
+            print(to_rows(&self.default_apps));
+            if !self.added_associations.is_empty() {
+                println!("Added Associations");
+                print(to_rows(&self.added_associations));
+            }
+        } else {
+            print(to_rows(&self.default_apps));
+        }
+
+        Ok(())
+    }
+}
+
+/// Join handlers for display over as many lines of up to `width` chars as
+/// needed, only going over for a single handler longer than that
+fn wrap_handlers<T: Display>(
+    handlers: &VecDeque<T>,
+    width: usize,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for (i, handler) in handlers.iter().enumerate() {
+        let mut handler = handler.to_string();
+        if i + 1 < handlers.len() {
+            handler.push(',');
+        }
+        if !line.is_empty() && line.len() + 1 + handler.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&handler);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Color the first column of a formatted table, leaving its padding alone
+fn color_mimes(table: &str) -> String {
+    table
+        .lines()
+        .map(|line| {
+            let cell = line
+                .strip_prefix("│ ")
+                .and_then(|rest| rest.split_once(" │"));
+            match cell {
+                Some((cell, rest)) if !cell.trim().is_empty() => {
+                    let mime = cell.trim_end();
+                    format!(
+                        "│ \x1b[36m{}\x1b[0m{} │{}\n",
+                        mime,
+                        &cell[mime.len()..],
+                        rest
+                    )
+                }
+                _ => format!("{}\n", line),
+            }
+        })
+        .collect()
+}
+
+/// Join handlers for display, eliding any past `max` (0 for no limit)
+fn join_truncated<T: Display>(handlers: &VecDeque<T>, max: usize) -> String {
+    use itertools::Itertools;
+
+    if max == 0 || handlers.len() <= max {
+        return handlers.iter().join(", ");
+    }
+    format!(
+        "{} (+{} more)",
+        handlers.iter().take(max).join(", "),
+        handlers.len() - max
+    )
+}
+
+impl FromStr for MimeApps {
+    type Err = Error;
+    fn from_str(raw_conf: &str) -> Result<Self> {
+        // editors on Windows may start the file with a byte order mark
+        let raw_conf = raw_conf.strip_prefix('\u{feff}').unwrap_or(raw_conf);
+        let file = Self::parse(Rule::file, raw_conf)
+            .map_err(Box::new)?
+            .next()
+            .unwrap();
+
+        let mut current_section = None;
+        let mut conf = Self::default();
+        let mut comments = Vec::new();
+
+        let mut last_line = 0;
+        for line in file.into_inner() {
+            let line_no = line.as_span().start_pos().line_col().0;
+            let after_blank = line_no > last_line + 1;
+            last_line = line_no;
+
+            // lines in sections handlr doesn't know are kept as they are,
+            // except comments that may belong to the next section:
+            // those directly above it
+            if let Some(Block::Unknown(lines)) = conf.layout.last_mut() {
+                if after_blank {
+                    lines.append(&mut comments);
+                }
+                if matches!(line.as_rule(), Rule::property | Rule::other) {
+                    lines.append(&mut comments);
+                    lines.push(line.as_str().to_owned());
+                    continue;
+                }
+            }
+
             match line.as_rule() {
+                Rule::comment => comments.push(line.as_str().to_owned()),
                 Rule::section => {
-                    current_section_name = line.into_inner().concat();
+                    if !comments.is_empty() {
+                        conf.layout.push(Block::Comments(std::mem::take(
+                            &mut comments,
+                        )));
+                    }
+                    let title = line.clone().into_inner().concat();
+                    current_section = Section::from_title(&title);
+                    match current_section {
+                        Some(section) => {
+                            if !conf.layout.contains(&Block::Section(section)) {
+                                conf.layout.push(Block::Section(section));
+                            }
+                        }
+                        None => conf.layout.push(Block::Unknown(vec![line
+                            .as_str()
+                            .to_owned()])),
+                    }
                 }
                 Rule::property => {
                     let mut inner_rules = line.into_inner(); // { name ~ "=" ~ value }
 
                     let name = inner_rules.next().unwrap().as_str();
+                    let value = inner_rules.next().unwrap().as_str();
                     let handlers = {
                         use itertools::Itertools;
 
-                        inner_rules
-                            .next()
-                            .unwrap()
-                            .as_str()
-                            .split(";")
+                        // The spec only allows ';', but hand-edited files
+                        // sometimes use ','. Accept both; save() always
+                        // writes ';'.
+                        value
+                            .split(&[';', ','][..])
                             .filter(|s| !s.is_empty())
                             .unique()
-                            .filter_map(|s| Handler::from_str(s).ok())
+                            .map(|s| Handler::assume_valid(s.into()))
                             .collect::<VecDeque<_>>()
                     };
 
-                    if !handlers.is_empty() {
-                        match (
-                            Mime::from_str(name),
-                            current_section_name.as_str(),
-                        ) {
-                            (Ok(mime), "Added Associations") => {
-                                conf.added_associations.insert(mime, handlers)
-                            }
+                    let section = match current_section {
+                        Some(section) => section,
+                        None => continue,
+                    };
+                    if value.contains(',') {
+                        conf.comma_separated.push((section, name.to_owned()));
+                    }
 
-                            (Ok(mime), "Removed Associations") => {
-                                conf.removed_associations.insert(mime, handlers)
-                            }
+                    // Key on the essence, so that text/plain and
+                    // text/plain;charset=utf-8 (written by different tools)
+                    // merge into one list instead of clobbering each other.
+                    let mime = Mime::from_str(name)
+                        .and_then(|mime| Mime::from_str(mime.essence_str()));
 
-                            (Ok(mime), "Default Applications") => {
-                                conf.default_apps.insert(mime, handlers)
+                    let mime = match mime {
+                        Ok(mime) => mime,
+                        Err(_) => {
+                            conf.invalid_mimes.push((section, name.to_owned()));
+                            continue;
+                        }
+                    };
+                    // Mime lowercases what it parses
+                    if name != mime.essence_str()
+                        && name.eq_ignore_ascii_case(mime.essence_str())
+                    {
+                        conf.spellings
+                            .insert((section, mime.clone()), name.to_owned());
+                    }
+                    if !handlers.is_empty() {
+                        if !comments.is_empty() {
+                            conf.comments
+                                .entry((section, mime.clone()))
+                                .or_default()
+                                .append(&mut comments);
+                        }
+                        let merged =
+                            conf.section_mut(section).entry(mime).or_default();
+                        for handler in handlers {
+                            if !merged.contains(&handler) {
+                                merged.push_back(handler);
                             }
-                            _ => None,
-                        };
+                        }
                     }
                 }
+                Rule::other => {
+                    return Err(Box::new(pest::error::Error::new_from_span(
+                        pest::error::ErrorVariant::CustomError {
+                            message: "expected a mime=handlers line".into(),
+                        },
+                        line.as_span(),
+                    ))
+                    .into());
+                }
                 _ => {}
             }
-        });
+        }
+
+        if !comments.is_empty() {
+            conf.layout.push(Block::Comments(comments));
+        }
 
         Ok(conf)
     }
-    pub fn save(&self) -> Result<()> {
-        use itertools::Itertools;
-        use std::io::prelude::*;
-        use std::io::BufWriter;
+}
 
-        let af = AtomicFile::new(
-            &Self::path()?,
-            OverwriteBehavior::AllowOverwrite,
-            Durability::DontSyncDir,
-        );
-        af.write(|f| -> Result<()> {
-            let mut writer = BufWriter::new(f);
-
-            #[rustfmt::skip]
-            let mut write_section = |
-                title,
-                items: &HashMap<Mime, VecDeque<Handler>>,
-            | -> Result<()> {
-                writer.write_all(title)?;
-                for (k, v) in items.iter().sorted() {
-                    writer.write_all(k.essence_str().as_ref())?;
-                    writer.write_all(b"=")?;
-                    writer.write_all(v.iter().join(";").as_ref())?;
-                    writer.write_all(b";\n")?;
-                }
-                Ok(())
+/// The changed lines between two files, prefixed with `-` or `+`, under the
+/// section header they belong to
+fn line_diff(before: &str, after: &str) -> String {
+    let before = before.lines().collect::<Vec<_>>();
+    let after = after.lines().collect::<Vec<_>>();
+
+    // lcs[i][j] is the longest common subsequence of before[i..], after[j..]
+    let mut lcs = vec![vec![0; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            lcs[i][j] = if before[i] == after[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
             };
+        }
+    }
 
-            write_section(b"[Added Associations]\n", &self.added_associations)?;
+    let mut out = String::new();
+    let mut header = None;
+    fn push(
+        out: &mut String,
+        header: &mut Option<&str>,
+        sign: char,
+        line: &str,
+    ) {
+        if let Some(header) = header.take() {
+            out.push_str(&format!(" {}\n", header));
+        }
+        out.push(sign);
+        out.push_str(line);
+        out.push('\n');
+    }
+    let is_header = |line: &str| line.starts_with('[');
 
-            if !self.removed_associations.is_empty() {
-                write_section(
-                    b"\n[Removed Associations]\n",
-                    &self.removed_associations,
-                )?;
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() || j < after.len() {
+        if i < before.len() && j < after.len() && before[i] == after[j] {
+            if is_header(before[i]) {
+                header = Some(before[i]);
             }
+            i += 1;
+            j += 1;
+        } else if i < before.len()
+            && (j == after.len() || lcs[i + 1][j] >= lcs[i][j + 1])
+        {
+            if is_header(before[i]) {
+                header = None;
+            }
+            push(&mut out, &mut header, '-', before[i]);
+            i += 1;
+        } else {
+            if is_header(after[j]) {
+                header = None;
+            }
+            push(&mut out, &mut header, '+', after[j]);
+            j += 1;
+        }
+    }
+    out
+}
 
-            write_section(b"\n[Default Applications]\n", &self.default_apps)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            writer.flush()?;
-            Ok(())
-        })
-        .map_err(|e| match e {
-            AtomicSaveError::Internal(e) => Error::Io(e),
-            AtomicSaveError::User(e) => e,
-        })?;
+    // Resolve handlers against the desktop files in tests/applications
+    fn init() {
+        std::env::set_var(
+            "XDG_DATA_HOME",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests"),
+        );
+    }
+
+    #[test]
+    fn test() -> Result<()> {
         Ok(())
     }
-    pub fn print(&self, detailed: bool) -> Result<()> {
-        use itertools::Itertools;
 
-        let to_rows = |map: &HashMap<Mime, VecDeque<Handler>>| {
-            map.iter()
-                .sorted()
-                .map(|(k, v)| vec![k.to_string(), v.iter().join(", ")])
-                .collect::<Vec<_>>()
+    #[test]
+    fn parse_error() {
+        "[Default Applications]\n=broken\n"
+            .parse::<MimeApps>()
+            .unwrap_err();
+        "[Default Applications]\ntext/plain=\n"
+            .parse::<MimeApps>()
+            .unwrap_err();
+    }
+
+    #[test]
+    fn crlf() -> Result<()> {
+        init();
+        let raw = include_str!("../../tests/crlf.list");
+        assert!(raw.contains("\r\n"));
+
+        let crlf = raw.parse::<MimeApps>()?;
+        let lf = raw.replace("\r\n", "\n").parse::<MimeApps>()?;
+        assert_eq!(crlf.default_apps, lf.default_apps);
+        assert_eq!(crlf.added_associations, lf.added_associations);
+
+        let html = &crlf.default_apps[&"text/html".parse::<Mime>()?];
+        assert_eq!(
+            html.iter().map(|h| h.to_string()).collect::<Vec<_>>(),
+            ["firefox.desktop", "nvim.desktop"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn bom() -> Result<()> {
+        init();
+        let raw = include_str!("../../tests/crlf.list").replace("\r\n", "\n");
+
+        let bom = format!("\u{feff}{}", raw).parse::<MimeApps>()?;
+        let lf = raw.parse::<MimeApps>()?;
+        assert_eq!(bom.default_apps, lf.default_apps);
+        assert_eq!(bom.added_associations, lf.added_associations);
+
+        // and with CRLF line endings on top
+        let both = format!("\u{feff}{}", raw.replace('\n', "\r\n"))
+            .parse::<MimeApps>()?;
+        assert_eq!(both.default_apps, lf.default_apps);
+
+        assert!(!bom.to_string()?.contains('\u{feff}'));
+        Ok(())
+    }
+
+    #[test]
+    fn block() -> Result<()> {
+        init();
+        let mut apps = "[Added Associations]
+text/html=nvim.desktop;firefox.desktop;
+
+[Default Applications]
+text/html=firefox.desktop;
+text/plain=firefox.desktop;nvim.desktop;
+"
+        .parse::<MimeApps>()?;
+        let html = "text/html".parse::<Mime>()?;
+        let firefox = Handler::from_str("firefox.desktop")?;
+
+        apps.block_handler(html.clone(), firefox.clone());
+        apps.block_handler(html.clone(), firefox.clone());
+
+        assert!(!apps.default_apps.contains_key(&html));
+        assert_eq!(
+            apps.added_associations[&html],
+            [Handler::from_str("nvim.desktop")?]
+        );
+        // other mimes are untouched
+        assert_eq!(apps.default_apps[&mime::TEXT_PLAIN][0], firefox);
+        assert_eq!(apps.removed_associations[&html], [firefox]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_association() -> Result<()> {
+        init();
+        let mut apps = "[Default Applications]\ntext/html=firefox.desktop;\n"
+            .parse::<MimeApps>()?;
+        let html = "text/html".parse::<Mime>()?;
+        let nvim = Handler::from_str("nvim.desktop")?;
+
+        apps.remove_association(html.clone(), nvim.clone());
+        apps.remove_association(html.clone(), nvim.clone());
+
+        let reread = apps.to_string()?.parse::<MimeApps>()?;
+        assert_eq!(reread.removed_associations[&html], [nvim]);
+        // unlike block, the default is left alone
+        assert_eq!(
+            reread.default_apps[&html],
+            [Handler::from_str("firefox.desktop")?]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn unset_everywhere() -> Result<()> {
+        init();
+        let mut apps = "[Default Applications]
+text/html=firefox.desktop;nvim.desktop;
+text/plain=nvim.desktop;
+image/png=firefox.desktop;
+"
+        .parse::<MimeApps>()?;
+        let nvim = Handler::from_str("nvim.desktop")?;
+
+        assert_eq!(
+            apps.unset_handler_everywhere(&nvim),
+            ["text/html".parse::<Mime>()?, mime::TEXT_PLAIN]
+        );
+        assert!(!apps.default_apps.contains_key(&mime::TEXT_PLAIN));
+        assert_eq!(
+            apps.default_apps[&mime::TEXT_HTML],
+            [Handler::from_str("firefox.desktop")?]
+        );
+        assert!(apps.unset_handler_everywhere(&nvim).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn clean() -> Result<()> {
+        init();
+        let mut apps = "[Added Associations]
+text/html=uninstalled.desktop;
+
+[Default Applications]
+text/plain=uninstalled.desktop;nvim.desktop;
+image/png=firefox.desktop;
+"
+        .parse::<MimeApps>()?;
+        let uninstalled = Handler::assume_valid("uninstalled.desktop".into());
+
+        assert_eq!(
+            apps.clean(),
+            [
+                (Section::Added, mime::TEXT_HTML, uninstalled.clone()),
+                (Section::Default, mime::TEXT_PLAIN, uninstalled)
+            ]
+        );
+        assert!(apps.added_associations.is_empty());
+        assert_eq!(
+            apps.default_apps[&mime::TEXT_PLAIN],
+            [Handler::from_str("nvim.desktop")?]
+        );
+        assert_eq!(apps.default_apps.len(), 2);
+        assert!(apps.clean().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn validate() -> Result<()> {
+        init();
+        let apps = "[Added Associations]
+text/html=hidden.desktop;firefox.desktop;
+text/markdown=nvim.desktop,firefox.desktop;
+
+[Removed Associations]
+text/html=uninstalled.desktop;
+
+[Default Applications]
+text/plain=uninstalled.desktop;nvim.desktop;
+not a mime=nvim.desktop;
+image/png=nodisplay.desktop;
+"
+        .parse::<MimeApps>()?;
+        let problem = |section: Section, mime: &str, handler, issue| Problem {
+            section: section.title(),
+            mime: mime.to_owned(),
+            handler,
+            issue,
         };
 
-        let table = ascii_table::AsciiTable::default();
+        assert_eq!(
+            apps.validate(),
+            [
+                problem(
+                    Section::Added,
+                    "text/html",
+                    Some("hidden.desktop".into()),
+                    "Hidden=true"
+                ),
+                problem(
+                    Section::Added,
+                    "text/markdown",
+                    None,
+                    "handlers separated by ',' instead of ';', run `handlr fix`"
+                ),
+                problem(
+                    Section::Default,
+                    "image/png",
+                    Some("nodisplay.desktop".into()),
+                    "NoDisplay=true"
+                ),
+                problem(
+                    Section::Default,
+                    "not a mime",
+                    None,
+                    "not a valid mime"
+                ),
+                problem(
+                    Section::Default,
+                    "text/plain",
+                    Some("uninstalled.desktop".into()),
+                    "desktop file not found"
+                ),
+            ]
+        );
+        Ok(())
+    }
 
-        if detailed {
-            println!("Default Apps");
-            table.print(to_rows(&self.default_apps));
-            if !self.added_associations.is_empty() {
-                println!("Added Associations");
-                table.print(to_rows(&self.added_associations));
-            }
-        } else {
-            table.print(to_rows(&self.default_apps));
+    #[test]
+    fn durability() -> Result<()> {
+        init();
+        let dir = tempfile::tempdir()?;
+        let apps = "[Default Applications]
+text/plain=nvim.desktop;
+"
+        .parse::<MimeApps>()?;
+
+        for (name, durability) in [
+            ("fast.list", Durability::DontSyncDir),
+            ("durable.list", Durability::SyncDir),
+        ] {
+            let path = dir.path().join(name);
+            apps.save_to(&path, durability)?;
+            assert_eq!(std::fs::read_to_string(&path)?, apps.to_string()?);
         }
+        Ok(())
+    }
+
+    #[test]
+    fn dry_run_diff() {
+        let before = "[Added Associations]
+text/html=firefox.desktop;
 
+[Default Applications]
+image/png=feh.desktop;
+text/plain=nvim.desktop;
+";
+        let after = "[Added Associations]
+text/html=firefox.desktop;
+
+[Default Applications]
+image/png=feh.desktop;
+text/plain=gedit.desktop;
+
+[Removed Associations]
+text/html=chromium.desktop;
+";
+        assert_eq!(
+            line_diff(before, after),
+            " [Default Applications]
+-text/plain=nvim.desktop;
++text/plain=gedit.desktop;
++
++[Removed Associations]
++text/html=chromium.desktop;
+"
+        );
+        assert!(line_diff(before, before).is_empty());
+    }
+
+    #[test]
+    fn handled_by() -> Result<()> {
+        init();
+        let apps = "[Added Associations]
+image/png=nvim.desktop;
+
+[Default Applications]
+text/plain=nvim.desktop;
+text/html=firefox.desktop;nvim.desktop;
+"
+        .parse::<MimeApps>()?;
+        let nvim = Handler::from_str("nvim.desktop")?;
+
+        assert_eq!(
+            apps.handled_by(&nvim, false),
+            [
+                (Section::Default, mime::TEXT_HTML, 1),
+                (Section::Default, mime::TEXT_PLAIN, 0)
+            ]
+        );
+        assert_eq!(
+            apps.handled_by(&nvim, true)[2],
+            (Section::Added, mime::IMAGE_PNG, 0)
+        );
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn add_twice() -> Result<()> {
+        init();
+        let mut apps = MimeApps::default();
+        let nvim = Handler::from_str("nvim.desktop")?;
+        apps.add_handler(mime::TEXT_PLAIN, nvim.clone(), false);
+        apps.add_handler(mime::TEXT_PLAIN, nvim, false);
+        apps.default_apps.insert(mime::TEXT_HTML, VecDeque::new());
+
+        let written = apps.to_string()?;
+        assert!(written.contains("\ntext/plain=nvim.desktop;\n"));
+        assert!(!written.contains("text/html"));
+        written.parse::<MimeApps>()?;
+        Ok(())
+    }
 
     #[test]
-    fn test() -> Result<()> {
+    fn add_first() -> Result<()> {
+        init();
+        let mut apps = "[Default Applications]
+text/html=firefox.desktop;nvim.desktop;
+"
+        .parse::<MimeApps>()?;
+        let firefox = Handler::from_str("firefox.desktop")?;
+        let nvim = Handler::from_str("nvim.desktop")?;
+
+        apps.add_handler(mime::TEXT_HTML, nvim.clone(), true);
+        assert_eq!(
+            apps.default_apps[&mime::TEXT_HTML],
+            [nvim, firefox.clone()]
+        );
+        apps.add_handler(mime::TEXT_PLAIN, firefox.clone(), true);
+        assert_eq!(apps.default_apps[&mime::TEXT_PLAIN], [firefox]);
+        Ok(())
+    }
+
+    #[test]
+    fn desktop_paths() -> Result<()> {
+        let _env = crate::utils::ENV_LOCK.lock().unwrap();
+        std::env::set_var("XDG_CURRENT_DESKTOP", "KDE:GNOME");
+        let paths = MimeApps::paths()?;
+        std::env::remove_var("XDG_CURRENT_DESKTOP");
+
+        let names = paths
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["kde-mimeapps.list", "gnome-mimeapps.list", "mimeapps.list"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn desktop_list_not_saved() -> Result<()> {
+        init();
+        let dir = tempfile::tempdir()?;
+        let plain = "# my comment
+[Default Applications]
+text/plain=nvim.desktop;
+
+[X-Custom]
+key=value
+";
+        std::fs::write(dir.path().join("mimeapps.list"), plain)?;
+        std::fs::write(
+            dir.path().join("gnome-mimeapps.list"),
+            "[Default Applications]\ntext/html=firefox.desktop;\n",
+        )?;
+
+        let _env = crate::utils::ENV_LOCK.lock().unwrap();
+        std::env::set_var("XDG_CONFIG_HOME", dir.path());
+        std::env::set_var("XDG_CURRENT_DESKTOP", "GNOME");
+        let res = (|| -> Result<_> {
+            let apps = MimeApps::read()?;
+            let (before, after) = MimeApps::read_others()?;
+            Ok((apps, before.len(), after.len()))
+        })();
+        std::env::remove_var("XDG_CURRENT_DESKTOP");
+        std::env::remove_var("XDG_CONFIG_HOME");
+        let (apps, before, after) = res?;
+
+        // the gnome list is only read for lookups
+        assert_eq!((before, after), (1, 0));
+        assert!(!apps.default_apps.contains_key(&mime::TEXT_HTML));
+        let mut written = Vec::new();
+        apps.write_to(&mut written)?;
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.starts_with(plain));
+        assert!(!written.contains("text/html"));
+        Ok(())
+    }
+
+    #[test]
+    fn mimeapps_env() -> Result<()> {
+        init();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("mimeapps.list");
+        let nvim = Handler::from_str("nvim.desktop")?;
+
+        // saving reads the config, which mustn't be the user's
+        let _env = crate::utils::ENV_LOCK.lock().unwrap();
+        std::env::set_var("HANDLR_MIMEAPPS", &path);
+        std::env::set_var("HANDLR_CONFIG", dir.path().join("handlr.toml"));
+        let res = (|| {
+            assert_eq!(MimeApps::paths()?.len(), 1);
+            assert_eq!(MimeApps::path()?, path);
+            let mut apps = MimeApps::read()?;
+            assert!(path.exists());
+            apps.set_handler(mime::TEXT_PLAIN, nvim.clone());
+            apps.save()?;
+            MimeApps::read()
+        })();
+        std::env::remove_var("HANDLR_CONFIG");
+        std::env::remove_var("HANDLR_MIMEAPPS");
+
+        assert_eq!(res?.default_apps[&mime::TEXT_PLAIN], [nvim]);
+        assert!(std::fs::read_to_string(&path)?
+            .contains("[Default Applications]\ntext/plain=nvim.desktop;\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn merge_params() -> Result<()> {
+        init();
+        let apps = "[Default Applications]
+text/plain=nvim.desktop;
+text/plain;charset=utf-8=firefox.desktop;nvim.desktop;
+"
+        .parse::<MimeApps>()?;
+
+        assert_eq!(apps.default_apps.len(), 1);
+        assert_eq!(
+            apps.default_apps[&mime::TEXT_PLAIN],
+            [
+                Handler::from_str("nvim.desktop")?,
+                Handler::from_str("firefox.desktop")?
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn comma_separated() -> Result<()> {
+        init();
+        let apps = "[Default Applications]
+text/html=firefox.desktop,nvim.desktop;
+"
+        .parse::<MimeApps>()?;
+
+        assert_eq!(
+            apps.default_apps[&mime::TEXT_HTML],
+            [
+                Handler::from_str("firefox.desktop")?,
+                Handler::from_str("nvim.desktop")?
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff() -> Result<()> {
+        init();
+        let old = "[Default Applications]
+text/html=firefox.desktop;
+text/plain=nvim.desktop;
+text/markdown=nvim.desktop;
+"
+        .parse::<MimeApps>()?;
+        let new = "[Removed Associations]
+text/html=nvim.desktop;
+
+[Default Applications]
+text/html=firefox.desktop;
+text/plain=firefox.desktop;nvim.desktop;
+"
+        .parse::<MimeApps>()?;
+
+        let changes = new
+            .diff(&old)
+            .iter()
+            .map(|c| format!("[{}] {}", c.section, c))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [
+                "[Removed Associations] + text/html=nvim.desktop;",
+                "[Default Applications] - text/markdown=nvim.desktop;",
+                "[Default Applications] ~ text/plain: nvim.desktop; -> \
+                 firefox.desktop;nvim.desktop;",
+            ]
+        );
+        assert!(new.diff(&new).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn truncate_handlers() {
+        let handlers = (1..=10)
+            .map(|i| format!("app{}.desktop", i))
+            .collect::<VecDeque<_>>();
+
+        assert_eq!(
+            join_truncated(&handlers, 3),
+            "app1.desktop, app2.desktop, app3.desktop (+7 more)"
+        );
+        assert_eq!(join_truncated(&handlers, 10).matches(", ").count(), 9);
+        assert_eq!(join_truncated(&handlers, 0).matches(", ").count(), 9);
+    }
+
+    #[test]
+    fn wrap_long_handlers() {
+        let handlers = (1..=5)
+            .map(|i| format!("app{}.desktop", i))
+            .collect::<VecDeque<_>>();
+
+        assert_eq!(
+            wrap_handlers(&handlers, 30),
+            [
+                "app1.desktop, app2.desktop,",
+                "app3.desktop, app4.desktop,",
+                "app5.desktop"
+            ]
+        );
+        assert_eq!(wrap_handlers(&handlers, 200).len(), 1);
+        assert_eq!(wrap_handlers(&handlers, 5).len(), 5);
+        assert!(wrap_handlers(&VecDeque::<String>::new(), 30).is_empty());
+    }
+
+    #[test]
+    fn colored_table() {
+        let table = ascii_table::AsciiTable::default().format(vec![
+            vec!["text/plain", "nvim.desktop,"],
+            vec!["", "firefox.desktop"],
+            vec!["image/*", "feh.desktop"],
+        ]);
+        let colored = color_mimes(&table);
+
+        assert!(colored.contains("│ \x1b[36mtext/plain\x1b[0m │"));
+        assert!(colored.contains("│ \x1b[36mimage/*\x1b[0m    │"));
+        // the layout is the same once the escapes are gone
+        let plain = colored.replace("\x1b[36m", "").replace("\x1b[0m", "");
+        assert_eq!(plain, table);
+    }
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        init();
+        let raw = "# handlr test config
+[Added Associations]
+text/plain=nvim.desktop;firefox.desktop;
+x-scheme-handler/https=firefox.desktop;
+
+[Removed Associations]
+text/html=nvim.desktop;
+
+[Default Applications]
+# a missing app is kept as written
+image/png=uninstalled.desktop;
+text/*=nvim.desktop;
+text/html=firefox.desktop;
+
+[X-Custom]
+Key=value
+";
+        let apps = raw.parse::<MimeApps>()?;
+        assert_eq!(apps.to_string()?, raw);
+
+        let mut file = tempfile::NamedTempFile::new()?;
+        file.write_all(apps.to_string()?.as_bytes())?;
+        let reread = MimeApps::read_from(file.path())?;
+        assert_eq!(reread.default_apps, apps.default_apps);
+        assert_eq!(reread.added_associations, apps.added_associations);
+        assert_eq!(reread.removed_associations, apps.removed_associations);
+        assert_eq!(reread.to_string()?, raw);
+        Ok(())
+    }
+
+    #[test]
+    fn mixed_case() -> Result<()> {
+        init();
+        let raw = "[Added Associations]
+Text/Plain=nvim.desktop;
+
+[Default Applications]
+text/html=firefox.desktop;
+x-scheme-handler/MyApp=firefox.desktop;
+";
+        let mut apps = raw.parse::<MimeApps>()?;
+        let myapp = "x-scheme-handler/myapp".parse::<Mime>()?;
+        assert!(apps.default_apps.contains_key(&myapp));
+        assert_eq!(apps.to_string()?, raw);
+
+        apps.add_handler(myapp, Handler::from_str("nvim.desktop")?, false);
+        assert!(apps.to_string()?.contains(
+            "\nx-scheme-handler/MyApp=firefox.desktop;nvim.desktop;\n"
+        ));
+
+        // each section keeps its own spelling
+        let raw = "[Added Associations]
+Text/Plain=nvim.desktop;
+
+[Default Applications]
+text/plain=firefox.desktop;
+";
+        assert_eq!(raw.parse::<MimeApps>()?.to_string()?, raw);
+        Ok(())
+    }
+
+    #[test]
+    fn section_order() -> Result<()> {
+        init();
+        let unusual = "[Default Applications]
+text/html=firefox.desktop;
+
+[Removed Associations]
+text/plain=firefox.desktop;
+
+[Added Associations]
+text/plain=nvim.desktop;
+";
+        assert_eq!(unusual.parse::<MimeApps>()?.to_string()?, unusual);
+
+        // sections missing from the file follow in the usual order
+        assert_eq!(
+            "[Default Applications]\n"
+                .parse::<MimeApps>()?
+                .to_string()?,
+            "[Default Applications]\n\n[Added Associations]\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn preserve_comments() -> Result<()> {
+        init();
+        let commented = "# managed by hand, see notes.txt
+[Default Applications]
+# firefox is too slow for local files
+text/html=nvim.desktop;
+text/plain=nvim.desktop;
+
+[X-Custom]
+Some Key=with = odd; values!
+# kept verbatim too
+
+# about added associations
+[Added Associations]
+text/plain=firefox.desktop;
+# trailing comment
+";
+        assert_eq!(commented.parse::<MimeApps>()?.to_string()?, commented);
+
+        // a comment outlives the line it was attached to
+        let mut apps = commented.parse::<MimeApps>()?;
+        apps.default_apps.remove(&mime::TEXT_HTML);
+        assert!(apps
+            .to_string()?
+            .contains("# firefox is too slow for local files\n"));
+
         Ok(())
     }
 }
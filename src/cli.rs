@@ -1,32 +1,295 @@
-use crate::common::{Handler, MimeOrExtension};
+use crate::common::{Handler, MimeOrExtension, UserPath};
+use crate::Error;
+use std::path::PathBuf;
+use std::str::FromStr;
 
 #[derive(clap::Clap)]
 #[clap(global_setting = clap::AppSettings::DeriveDisplayOrder)]
 #[clap(global_setting = clap::AppSettings::DisableHelpSubcommand)]
 #[clap(version = clap::crate_version!())]
+pub struct Cli {
+    /// Save to the current desktop's mimeapps.list, like
+    /// gnome-mimeapps.list, instead of the plain one
+    #[clap(long, global = true)]
+    pub desktop: bool,
+
+    /// Print the changes to mimeapps.list instead of saving them
+    #[clap(long, global = true)]
+    pub dry_run: bool,
+
+    /// Warn about mistakes in the files read, and with `get`, also show the
+    /// Exec, Terminal, Path, Actions and MimeType of its desktop file
+    #[clap(long, short, global = true)]
+    pub verbose: bool,
+
+    /// End each printed field with a NUL byte, for `xargs -0`
+    #[clap(short = '0', long = "null", global = true)]
+    pub null: bool,
+
+    /// Read handlr's config from this file instead of
+    /// ~/.config/handlr/handlr.toml, also settable with $HANDLR_CONFIG
+    #[clap(long, global = true, value_hint = clap::ValueHint::FilePath)]
+    pub config: Option<PathBuf>,
+
+    /// Read and save associations in this file instead of
+    /// ~/.config/mimeapps.list, also settable with $HANDLR_MIMEAPPS
+    #[clap(long, global = true, value_hint = clap::ValueHint::FilePath)]
+    pub mimeapps: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    pub cmd: Cmd,
+}
+
+#[derive(clap::Clap)]
 pub enum Cmd {
     /// List default apps and the associated handlers
     List {
         #[clap(long, short)]
         all: bool,
+        /// Print mimeapps.list as handlr would save it, without saving
+        #[clap(long)]
+        raw: bool,
+        /// Print a JSON object mapping mimes to their handlers
+        #[clap(long, conflicts_with = "raw")]
+        json: bool,
+        /// Show every handler, wrapping long lists instead of truncating
+        #[clap(long, visible_alias = "no-truncate")]
+        wide: bool,
+        /// Color the mimes: always, never, or only on a terminal without
+        /// $NO_COLOR set
+        #[clap(
+            long,
+            default_value = "auto",
+            possible_values = &["auto", "always", "never"]
+        )]
+        color: ColorChoice,
+    },
+
+    /// Open paths/URLs with their default handlers
+    Open {
+        #[clap(required = true, value_hint = clap::ValueHint::AnyPath)]
+        paths: Vec<UserPath>,
+        /// Open them all with this handler, leaving the defaults alone
+        #[clap(long)]
+        with: Option<Handler>,
+        /// Run one of the handler's desktop actions, like `new-window`
+        #[clap(long)]
+        action: Option<String>,
     },
 
     /// Set the default handler for mime/extension
     Set {
-        mime: MimeOrExtension,
+        /// A mime, an extension, or a file name glob with --by-glob
+        mime: String,
         handler: Handler,
+        /// Additional mimes/extensions to set the same handler for
+        #[clap(long)]
+        mimes: Vec<String>,
+        /// Use the mime of files matching a glob like '*.md'
+        #[clap(long)]
+        by_glob: bool,
     },
 
+    /// Set a handler as the default for every mime its desktop file lists
+    SetAll { handler: Handler },
+
     /// Unset the default handler for mime/extension
     Unset { mime: MimeOrExtension },
 
-    /// Add a handler for given mime/extension
-    /// Note that the first handler is the default
+    /// Remove a handler from every mime it's a default for
+    UnsetAll { handler: Handler },
+
+    /// Add a fallback handler for given mime/extension
     Add {
+        /// A mime, an extension, or a file name glob with --by-glob
+        mime: String,
+        handler: Handler,
+        /// Make it the default, keeping the existing handlers as fallbacks
+        #[clap(long, visible_alias = "default")]
+        first: bool,
+        /// Use the mime of files matching a glob like '*.md'
+        #[clap(long)]
+        by_glob: bool,
+    },
+
+    /// Block a handler for given mime/extension, the inverse of `add`
+    Block {
         mime: MimeOrExtension,
         handler: Handler,
     },
 
+    /// Mask a handler for given mime/extension, even if the system adds it
+    Remove {
+        mime: MimeOrExtension,
+        handler: Handler,
+    },
+
+    /// Get handler for this mime/extension
+    Get {
+        mime: MimeOrExtension,
+        /// Print a JSON object instead of the desktop file name
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// List the mimes a handler is the default or a fallback for
+    WhichHandles {
+        handler: Handler,
+        /// Include added associations
+        #[clap(long, short)]
+        all: bool,
+    },
+
+    /// Launch the handler for a mime/extension with the given arguments
+    Launch {
+        mime: MimeOrExtension,
+        #[clap(value_hint = clap::ValueHint::AnyPath)]
+        args: Vec<String>,
+        /// Run one of the handler's desktop actions, like `new-window`
+        #[clap(long)]
+        action: Option<String>,
+    },
+
+    /// Print the mime type detected for files/URLs
+    Mime {
+        #[clap(required = true, value_hint = clap::ValueHint::AnyPath)]
+        paths: Vec<UserPath>,
+        /// Print a JSON array instead of `path<TAB>mime<TAB>guess` lines,
+        /// where the last field is `guess` for a missing file's name
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Remove associations whose desktop file was uninstalled
+    Clean,
+
     /// Fix the current MIME associations by merging and removing MIME aliases
     Fix,
+
+    /// Show how the associations changed since a saved mimeapps.list
+    Diff {
+        #[clap(value_hint = clap::ValueHint::FilePath)]
+        snapshot: PathBuf,
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Write the handler each mime resolves to, including the system's
+    /// associations, as a JSON object
+    Export {
+        /// Write to this file instead of stdout
+        #[clap(long, value_hint = clap::ValueHint::FilePath)]
+        path: Option<PathBuf>,
+    },
+
+    /// Set the defaults from a document written by `export`
+    Import {
+        #[clap(value_hint = clap::ValueHint::FilePath)]
+        path: PathBuf,
+        /// Keep the defaults of mimes the document doesn't mention
+        #[clap(long)]
+        merge: bool,
+    },
+
+    /// Report invalid mimes and missing, Hidden or NoDisplay handlers in
+    /// mimeapps.list, failing if there are any
+    Validate {
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Edit a copy of mimeapps.list in $EDITOR, saving it only if it parses
+    Edit,
+
+    /// Check that the config and mimeapps.list parse, without changing them
+    CheckConfig,
+
+    /// Inspect handlr's own configuration
+    Config {
+        #[clap(subcommand)]
+        cmd: ConfigCmd,
+    },
+
+    /// Print a completion script for the given shell
+    Completions {
+        #[clap(possible_values = &["bash", "zsh", "fish"])]
+        shell: Shell,
+    },
+
+    /// List mimes or desktop files for the completion scripts
+    #[clap(setting = clap::AppSettings::Hidden)]
+    Autocomplete {
+        /// Desktop files, with their names after a tab
+        #[clap(short = 'd')]
+        desktop_files: bool,
+        /// Mimes in mimeapps.list
+        #[clap(short = 'm')]
+        mimes: bool,
+    },
+}
+
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    /// The completion script, generated from the CLI. It asks
+    /// `handlr autocomplete` for mimes and desktop files as you type.
+    pub fn script(&self) -> String {
+        use clap::IntoApp;
+        crate::completions::generate(self, &Cli::into_app())
+    }
+}
+
+impl FromStr for Shell {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(Error::UnknownShell(s.to_owned())),
+        }
+    }
+}
+
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(&self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && atty::is(atty::Stream::Stdout)
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(Error::UnknownColor(s.to_owned())),
+        }
+    }
+}
+
+#[derive(clap::Clap)]
+pub enum ConfigCmd {
+    /// Print the effective config, including defaults for unset fields
+    Dump,
+    /// Edit a copy of the config in $EDITOR, saving it only if it parses
+    Edit,
 }
@@ -21,7 +21,6 @@ pub enum OverwriteBehavior {
 /// Regardless of the option you pick, the atomic write will be consistent after a crash
 /// (will never contain partially-written data).
 #[derive(Clone, Copy)]
-#[allow(dead_code)]
 pub enum Durability {
     /// Faster, ensures either old or new file contents (but not half-written data)
     /// will be present after system crash.
@@ -72,7 +71,7 @@ impl<E: ErrorTrait> ErrorTrait for AtomicSaveError<E> {
 fn safe_parent(p: &path::Path) -> Option<&path::Path> {
     match p.parent() {
         None => None,
-        Some(x) if x.as_os_str().len() == 0 => Some(&path::Path::new(".")),
+        Some(x) if x.as_os_str().is_empty() => Some(path::Path::new(".")),
         x => x,
     }
 }
@@ -180,15 +179,14 @@ mod imp {
             Ok(()) => Ok(()),
             Err(nix::Error::Sys(errno)) => Err(errno.into()),
             Err(nix::Error::InvalidPath) => {
-                Err(io::Error::new(io::ErrorKind::Other, "invalid path"))
+                Err(io::Error::other("invalid path"))
             }
             Err(nix::Error::InvalidUtf8) => {
-                Err(io::Error::new(io::ErrorKind::Other, "invalid utf-8"))
+                Err(io::Error::other("invalid utf-8"))
+            }
+            Err(nix::Error::UnsupportedOperation) => {
+                Err(io::Error::other("unsupported operation"))
             }
-            Err(nix::Error::UnsupportedOperation) => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "unsupported operation",
-            )),
         }
     }
 
@@ -0,0 +1,637 @@
+use crate::config::CONFIG;
+use crate::utils;
+use crate::{Error, Result};
+use freedesktop_entry_parser::{AttrSelector, Entry};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+/// The parts of a .desktop file needed to launch it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DesktopEntry {
+    /// Where the entry was read from, passed to the app by `%k`
+    pub path: PathBuf,
+    pub name: String,
+    pub exec: String,
+    pub icon: Option<String>,
+    pub terminal: bool,
+    /// Working directory to run the program in
+    pub working_dir: Option<PathBuf>,
+    /// The entry was deleted, and should be treated as not installed
+    pub hidden: bool,
+    pub no_display: bool,
+    /// Launch over D-Bus with org.freedesktop.Application, not Exec
+    pub dbus_activatable: bool,
+    /// A binary that must exist for the entry to be usable
+    pub try_exec: Option<String>,
+    /// The [Desktop Action] groups listed in Actions=, in order
+    pub actions: Vec<Action>,
+    /// The MimeType= list, unparsed
+    pub mime_types: Vec<String>,
+}
+
+/// An alternative way to launch an app, like opening a private window
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Action {
+    pub id: String,
+    pub name: String,
+    pub exec: String,
+}
+
+impl DesktopEntry {
+    pub fn read(path: &Path) -> Result<Self> {
+        Self::parse(path, std::fs::read(path)?)
+    }
+
+    /// Every launchable desktop file in the XDG data directories, sorted.
+    /// Files in more important directories hide those of the same name.
+    pub fn installed() -> Result<Vec<Self>> {
+        let dirs = xdg::BaseDirectories::new()?;
+        let mut seen = HashSet::new();
+        let mut entries = Vec::new();
+
+        for dir in
+            std::iter::once(dirs.get_data_home()).chain(dirs.get_data_dirs())
+        {
+            let files = match std::fs::read_dir(dir.join("applications")) {
+                Ok(files) => files,
+                Err(_) => continue,
+            };
+            for file in files.flatten() {
+                let path = file.path();
+                if path.extension() != Some("desktop".as_ref())
+                    || !seen.insert(file.file_name())
+                {
+                    continue;
+                }
+                match Self::read(&path) {
+                    Ok(entry) if entry.is_usable() => entries.push(entry),
+                    _ => {}
+                }
+            }
+        }
+
+        entries.sort_by(|a, b| a.path.file_name().cmp(&b.path.file_name()));
+        Ok(entries)
+    }
+
+    /// Not deleted with Hidden=true, and any TryExec binary is installed
+    pub fn is_usable(&self) -> bool {
+        !self.hidden
+            && self
+                .try_exec
+                .as_deref()
+                .is_none_or(|bin| utils::find_in_path(bin).is_some())
+    }
+
+    /// The desktop file name, which mimeapps.list refers to it by
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn parse(path: &Path, raw: Vec<u8>) -> Result<Self> {
+        let bad_entry = || Error::BadEntry(path.to_owned());
+        let entry = Entry::parse(raw).map_err(|_| bad_entry())?;
+        let section = entry.section("Desktop Entry");
+        let dbus_activatable = section.attr("DBusActivatable") == Some("true");
+        // Exec is optional for entries launched over D-Bus
+        let exec = match section.attr("Exec") {
+            Some(exec) => exec,
+            None if dbus_activatable => "",
+            None => return Err(bad_entry()),
+        };
+
+        Ok(Self {
+            path: path.to_owned(),
+            name: localized(&section, "Name").unwrap_or_default().to_owned(),
+            exec: exec.to_owned(),
+            icon: section.attr("Icon").map(str::to_owned),
+            terminal: section.attr("Terminal") == Some("true"),
+            working_dir: section
+                .attr("Path")
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from),
+            hidden: section.attr("Hidden") == Some("true"),
+            no_display: section.attr("NoDisplay") == Some("true"),
+            dbus_activatable,
+            try_exec: section.attr("TryExec").map(str::to_owned),
+            actions: section
+                .attr("Actions")
+                .unwrap_or_default()
+                .split(';')
+                .filter(|id| !id.is_empty())
+                .filter_map(|id| {
+                    let group = entry.section(format!("Desktop Action {}", id));
+                    Some(Action {
+                        id: id.to_owned(),
+                        name: localized(&group, "Name")
+                            .unwrap_or_default()
+                            .to_owned(),
+                        exec: group.attr("Exec")?.to_owned(),
+                    })
+                })
+                .collect(),
+            mime_types: section
+                .attr("MimeType")
+                .unwrap_or_default()
+                .split(';')
+                .filter(|mime| !mime.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        })
+    }
+
+    /// Split the Exec line and expand its field codes, passing `args`
+    /// wherever the entry accepts files or URLs.
+    ///
+    /// `%f` and `%u` take a single file or URL, so an entry using them gets
+    /// one command per argument. `%F` and `%U` receive every argument.
+    pub fn get_cmd(&self, args: &[String]) -> Result<Vec<Vec<String>>> {
+        let words = shlex::split(&self.exec)
+            .ok_or_else(|| Error::BadCmd(self.exec.clone()))?;
+
+        if args.len() > 1 && words.iter().any(|word| takes_single_arg(word)) {
+            return args
+                .iter()
+                .map(|arg| self.expand_words(&words, std::slice::from_ref(arg)))
+                .collect();
+        }
+        Ok(vec![self.expand_words(&words, args)?])
+    }
+
+    fn expand_words(
+        &self,
+        words: &[String],
+        args: &[String],
+    ) -> Result<Vec<String>> {
+        let mut cmd = Vec::with_capacity(words.len() + args.len());
+        for word in words {
+            match word.as_str() {
+                "%f" | "%F" | "%u" | "%U" => cmd.extend_from_slice(args),
+                "%i" => {
+                    if let Some(icon) = &self.icon {
+                        cmd.push("--icon".to_owned());
+                        cmd.push(icon.clone());
+                    }
+                }
+                _ => {
+                    let expanded = self.expand_codes(word, args);
+                    // a word made up only of deprecated codes disappears
+                    if !expanded.is_empty() || word.is_empty() {
+                        cmd.push(expanded);
+                    }
+                }
+            }
+        }
+
+        if cmd.is_empty() {
+            return Err(Error::BadCmd(self.exec.clone()));
+        }
+        Ok(cmd)
+    }
+
+    /// Expand field codes embedded in a larger word, like `--file=%f`
+    fn expand_codes(&self, word: &str, args: &[String]) -> String {
+        let mut expanded = String::with_capacity(word.len());
+        let mut chars = word.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                expanded.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('%') => expanded.push('%'),
+                Some('f' | 'F' | 'u' | 'U') => {
+                    expanded.push_str(&args.join(" "))
+                }
+                Some('i') => {
+                    expanded.push_str(self.icon.as_deref().unwrap_or_default())
+                }
+                Some('c') => expanded.push_str(&self.name),
+                Some('k') => expanded.push_str(&self.path.to_string_lossy()),
+                // %d %D %n %N %v %m are deprecated, drop them and
+                // anything else we don't recognize
+                _ => {}
+            }
+        }
+        expanded
+    }
+
+    /// The same entry, launching the action `id` with its own Exec line
+    pub fn with_action(&self, id: &str) -> Result<Self> {
+        let action =
+            self.actions.iter().find(|a| a.id == id).ok_or_else(|| {
+                let ids = self.actions.iter().map(|a| a.id.as_str());
+                Error::UnknownAction(
+                    self.file_name(),
+                    id.to_owned(),
+                    ids.collect::<Vec<_>>().join(", "),
+                )
+            })?;
+
+        Ok(Self {
+            exec: action.exec.clone(),
+            dbus_activatable: false,
+            ..self.clone()
+        })
+    }
+
+    /// The gdbus call asking a DBusActivatable app to open `args`, or just
+    /// to activate when there are none. Its D-Bus name is the desktop file
+    /// name without `.desktop`.
+    fn dbus_cmd(&self, args: &[String]) -> Result<Vec<String>> {
+        let file_name = self.file_name();
+        let app_id = file_name.trim_end_matches(".desktop");
+        let object_path =
+            format!("/{}", app_id.replace('.', "/").replace('-', "_"));
+
+        let mut cmd = [
+            "gdbus",
+            "call",
+            "--session",
+            "--dest",
+            app_id,
+            "--object-path",
+            &object_path,
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>();
+
+        if args.is_empty() {
+            cmd.push("--method=org.freedesktop.Application.Activate".into());
+        } else {
+            let uris = args
+                .iter()
+                .map(|arg| to_uri(arg).map(|uri| gvariant_string(&uri)))
+                .collect::<Result<Vec<_>>>()?;
+            cmd.push("--method=org.freedesktop.Application.Open".into());
+            cmd.push(format!("@as [{}]", uris.join(", ")));
+        }
+        // no platform data, like a startup notification id
+        cmd.push("@a{sv} {}".into());
+        Ok(cmd)
+    }
+
+    fn dbus_activate(&self, args: &[String]) -> Result<Child> {
+        let cmd = self.dbus_cmd(args)?;
+        let mut child = Command::new(&cmd[0])
+            .args(&cmd[1..])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        if !child.wait()?.success() {
+            return Err(Error::BadCmd(cmd.join(" ")));
+        }
+        Ok(child)
+    }
+
+    pub fn exec(&self, args: &[String]) -> Result<()> {
+        // apps that can't be reached over D-Bus usually still have an Exec
+        if self.dbus_activatable && self.dbus_activate(args).is_ok() {
+            return Ok(());
+        }
+
+        // a missing directory is ignored rather than failing the launch
+        let working_dir =
+            self.working_dir.as_deref().filter(|dir| dir.is_dir());
+        let args = match working_dir {
+            Some(_) => absolute_args(args)?,
+            None => args.to_vec(),
+        };
+
+        for mut cmd in self.get_cmd(&args)? {
+            if self.terminal {
+                let mut term = CONFIG.terminal_cmd()?;
+                term.push("-e".to_owned());
+                cmd.splice(0..0, term);
+            }
+            let mut cmd_builder = Command::new(&cmd[0]);
+            if let Some(dir) = working_dir {
+                cmd_builder.current_dir(dir);
+            }
+            cmd_builder
+                .args(&cmd[1..])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `word` has a `%f` or `%u`, which take a single argument
+fn takes_single_arg(word: &str) -> bool {
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' && matches!(chars.next(), Some('f' | 'u')) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Make relative paths absolute, so they still point at the same files from
+/// another working directory. URLs are left alone.
+fn absolute_args(args: &[String]) -> Result<Vec<String>> {
+    let cwd = std::env::current_dir()?;
+    Ok(args
+        .iter()
+        .map(|arg| {
+            if url::Url::parse(arg).is_ok() || Path::new(arg).is_absolute() {
+                arg.clone()
+            } else {
+                cwd.join(arg).to_string_lossy().into_owned()
+            }
+        })
+        .collect())
+}
+
+/// A URL as is, or a path as an absolute file:// URL
+fn to_uri(arg: &str) -> Result<String> {
+    if let Ok(url) = url::Url::parse(arg) {
+        return Ok(url.to_string());
+    }
+    let path = std::env::current_dir()?.join(arg);
+    url::Url::from_file_path(&path)
+        .map(|url| url.to_string())
+        .map_err(|_| Error::BadPath(arg.to_owned()))
+}
+
+/// Quote a string in GVariant text format
+fn gvariant_string(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Look up a localestring key like `Name[de_DE]=`, trying the current
+/// LC_MESSAGES locale from most to least specific, as the spec describes
+fn localized<'a, T: AsRef<str>>(
+    section: &'a AttrSelector<T>,
+    key: &str,
+) -> Option<&'a str> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+
+    locale_variants(&locale)
+        .iter()
+        .find_map(|variant| section.attr_with_param(key, variant))
+        .or_else(|| section.attr(key))
+}
+
+/// `lang_COUNTRY.ENCODING@MODIFIER` as the keys to try, in order
+fn locale_variants(locale: &str) -> Vec<String> {
+    let (locale, modifier) = match locale.split_once('@') {
+        Some((locale, modifier)) => (locale, Some(modifier)),
+        None => (locale, None),
+    };
+    let locale = locale.split('.').next().unwrap_or_default();
+    let (lang, country) = match locale.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (locale, None),
+    };
+    if lang.is_empty() || lang == "C" || lang == "POSIX" {
+        return Vec::new();
+    }
+
+    let mut variants = Vec::new();
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        variants.push(format!("{}_{}@{}", lang, country, modifier));
+    }
+    if let Some(country) = country {
+        variants.push(format!("{}_{}", lang, country));
+    }
+    if let Some(modifier) = modifier {
+        variants.push(format!("{}@{}", lang, modifier));
+    }
+    variants.push(lang.to_owned());
+    variants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(exec: &str) -> DesktopEntry {
+        let raw = format!(
+            "[Desktop Entry]\nName=My App\nIcon=myapp\nExec={}\n",
+            exec
+        );
+        DesktopEntry::parse(Path::new("/apps/myapp.desktop"), raw.into())
+            .unwrap()
+    }
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn url_list() -> Result<()> {
+        let urls = strings(&["https://example.com", "https://example.org"]);
+        assert_eq!(
+            entry("myapp --flag %U").get_cmd(&urls)?,
+            [strings(&[
+                "myapp",
+                "--flag",
+                "https://example.com",
+                "https://example.org"
+            ])]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn field_codes() -> Result<()> {
+        let files = strings(&["a.txt"]);
+        assert_eq!(
+            entry("myapp %i --title=%c %k --file=%f").get_cmd(&files)?,
+            [strings(&[
+                "myapp",
+                "--icon",
+                "myapp",
+                "--title=My App",
+                "/apps/myapp.desktop",
+                "--file=a.txt"
+            ])]
+        );
+        assert_eq!(
+            entry("myapp %d %D %n %N %v %m 100%% %F").get_cmd(&[])?,
+            [strings(&["myapp", "100%"])]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn single_file() -> Result<()> {
+        let files = strings(&["a.txt", "b.txt"]);
+        assert_eq!(
+            entry("myapp %f").get_cmd(&files)?,
+            [strings(&["myapp", "a.txt"]), strings(&["myapp", "b.txt"])]
+        );
+        assert_eq!(
+            entry("myapp --file=%f").get_cmd(&files)?,
+            [
+                strings(&["myapp", "--file=a.txt"]),
+                strings(&["myapp", "--file=b.txt"])
+            ]
+        );
+        // an escaped %% isn't a field code
+        assert_eq!(
+            entry("myapp --fmt=%%f %F").get_cmd(&files)?,
+            [strings(&["myapp", "--fmt=%f", "a.txt", "b.txt"])]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn installed() -> Result<()> {
+        std::env::set_var(
+            "XDG_DATA_HOME",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests"),
+        );
+        let names = DesktopEntry::installed()?
+            .iter()
+            .map(DesktopEntry::file_name)
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"firefox.desktop".to_owned()));
+        assert!(names.contains(&"nvim.desktop".to_owned()));
+        Ok(())
+    }
+
+    #[test]
+    fn locales() {
+        assert_eq!(
+            locale_variants("sr_YU.UTF-8@Latn"),
+            ["sr_YU@Latn", "sr_YU", "sr@Latn", "sr"]
+        );
+        assert_eq!(locale_variants("de"), ["de"]);
+        assert!(locale_variants("C.UTF-8").is_empty());
+
+        let raw = "[Desktop Entry]\nName=Files\nName[de]=Dateien\n\
+                   Name[de_AT]=Dateien (AT)\n";
+        let entry = Entry::parse(raw).unwrap();
+        let section = entry.section("Desktop Entry");
+        std::env::set_var("LC_ALL", "de_DE.UTF-8");
+        assert_eq!(localized(&section, "Name"), Some("Dateien"));
+        std::env::set_var("LC_ALL", "fr_FR.UTF-8");
+        assert_eq!(localized(&section, "Name"), Some("Files"));
+        std::env::remove_var("LC_ALL");
+    }
+
+    #[test]
+    fn mime_types() {
+        let raw = "[Desktop Entry]\nExec=eog %U\n\
+                   MimeType=image/png;image/jpeg;;\n";
+        let entry =
+            DesktopEntry::parse(Path::new("eog.desktop"), raw.into()).unwrap();
+        assert_eq!(entry.mime_types, ["image/png", "image/jpeg"]);
+        assert!(entry.get_cmd(&[]).is_ok());
+    }
+
+    #[test]
+    fn dbus_activation() -> Result<()> {
+        let raw = "[Desktop Entry]\nDBusActivatable=true\n";
+        let entry = DesktopEntry::parse(
+            Path::new("/apps/org.gnome.Evince-Previewer.desktop"),
+            raw.into(),
+        )?;
+        assert!(entry.dbus_activatable);
+
+        let cmd = entry.dbus_cmd(&strings(&["/tmp/it's.pdf"]))?;
+        assert_eq!(
+            cmd[4..],
+            strings(&[
+                "org.gnome.Evince-Previewer",
+                "--object-path",
+                "/org/gnome/Evince_Previewer",
+                "--method=org.freedesktop.Application.Open",
+                "@as ['file:///tmp/it\\'s.pdf']",
+                "@a{sv} {}"
+            ])
+        );
+        assert_eq!(
+            entry.dbus_cmd(&[])?[7..],
+            strings(&[
+                "--method=org.freedesktop.Application.Activate",
+                "@a{sv} {}"
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn working_dir() -> Result<()> {
+        let raw = "[Desktop Entry]\nExec=myapp %f\nPath=/opt/myapp\n";
+        let myapp =
+            DesktopEntry::parse(Path::new("myapp.desktop"), raw.into())?;
+        assert_eq!(myapp.working_dir.as_deref(), Some(Path::new("/opt/myapp")));
+        assert_eq!(entry("myapp").working_dir, None);
+
+        let cwd = std::env::current_dir()?;
+        assert_eq!(
+            absolute_args(&strings(&["a.txt", "/b.txt", "https://c.org"]))?,
+            strings(&[
+                &cwd.join("a.txt").to_string_lossy(),
+                "/b.txt",
+                "https://c.org"
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn actions() -> Result<()> {
+        let raw = "[Desktop Entry]
+Exec=firefox %u
+Actions=new-window;new-private-window;broken;
+
+[Desktop Action new-window]
+Name=New Window
+Exec=firefox --new-window %u
+
+[Desktop Action new-private-window]
+Name=New Private Window
+Exec=firefox --private-window %u
+
+[Desktop Action broken]
+Name=No Exec
+";
+        let firefox =
+            DesktopEntry::parse(Path::new("firefox.desktop"), raw.into())?;
+        assert_eq!(
+            firefox.actions.iter().map(|a| &a.id).collect::<Vec<_>>(),
+            ["new-window", "new-private-window"]
+        );
+        assert_eq!(firefox.actions[0].name, "New Window");
+
+        let urls = strings(&["https://example.com"]);
+        assert_eq!(
+            firefox.with_action("new-private-window")?.get_cmd(&urls)?,
+            [strings(&[
+                "firefox",
+                "--private-window",
+                "https://example.com"
+            ])]
+        );
+        assert!(matches!(
+            firefox.with_action("broken"),
+            Err(Error::UnknownAction(..))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn no_exec() {
+        let raw = "[Desktop Entry]\nName=My App\n";
+        assert!(matches!(
+            DesktopEntry::parse(Path::new("myapp.desktop"), raw.into()),
+            Err(Error::BadEntry(_))
+        ));
+    }
+}
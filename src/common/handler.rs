@@ -1,4 +1,6 @@
+use super::DesktopEntry;
 use crate::{Error, Result};
+use serde::Serialize;
 use std::ffi::OsString;
 use std::fmt::Display;
 use std::path::PathBuf;
@@ -20,15 +22,140 @@ impl FromStr for Handler {
     }
 }
 
+/// What the handler's desktop file says about running it, for `get --verbose`
+#[derive(Debug, PartialEq, Serialize)]
+pub struct HandlerInfo {
+    pub handler: String,
+    /// The desktop file it was read from
+    pub file: PathBuf,
+    pub exec: String,
+    pub terminal: bool,
+    /// The Path= working directory
+    pub working_dir: Option<PathBuf>,
+    /// The IDs of its [Desktop Action] groups, for `open --action`
+    pub actions: Vec<String>,
+    pub mime_types: Vec<String>,
+}
+
+impl Display for HandlerInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} ({})", self.handler, self.file.display())?;
+        writeln!(f, "Exec={}", self.exec)?;
+        writeln!(f, "Terminal={}", self.terminal)?;
+        if let Some(dir) = &self.working_dir {
+            writeln!(f, "Path={}", dir.display())?;
+        }
+        if !self.actions.is_empty() {
+            writeln!(f, "Actions={}", self.actions.join(";"))?;
+        }
+        write!(f, "MimeType={}", self.mime_types.join(";"))
+    }
+}
+
 impl Handler {
     pub fn get_path(name: &std::ffi::OsStr) -> Option<PathBuf> {
         let mut path = PathBuf::from("applications");
         path.push(name);
         xdg::BaseDirectories::new().ok()?.find_data_file(path)
     }
+    /// Check that the desktop file exists, hasn't been deleted with
+    /// Hidden=true and that its TryExec binary is installed
     pub fn resolve(name: OsString) -> Result<Self> {
-        let _path = Self::get_path(&name)
-            .ok_or(Error::NotFound(name.to_string_lossy().into()))?;
+        let not_found = || Error::NotFound(name.to_string_lossy().into(), None);
+        let path = Self::get_path(&name).ok_or_else(not_found)?;
+        if DesktopEntry::read(&path).is_ok_and(|entry| !entry.is_usable()) {
+            return Err(not_found());
+        }
         Ok(Self(name))
     }
+
+    /// Wrap a desktop file name without checking it, for names read from
+    /// mimeapps.list that should survive being saved again
+    pub fn assume_valid(name: OsString) -> Self {
+        Self(name)
+    }
+
+    pub fn is_installed(&self) -> bool {
+        Self::get_path(&self.0).is_some()
+    }
+
+    /// Whether the handler can be launched: installed and not Hidden
+    pub fn is_available(&self) -> bool {
+        Self::resolve(self.0.clone()).is_ok()
+    }
+
+    pub fn get_entry(&self) -> Result<DesktopEntry> {
+        let path = Self::get_path(&self.0)
+            .ok_or_else(|| Error::NotFound(self.to_string(), None))?;
+        DesktopEntry::read(&path)
+    }
+
+    /// The application's localized Name=, if its desktop file has one
+    pub fn name(&self) -> Option<String> {
+        let name = self.get_entry().ok()?.name;
+        Some(name).filter(|name| !name.is_empty())
+    }
+
+    pub fn info(&self) -> Result<HandlerInfo> {
+        let entry = self.get_entry()?;
+        Ok(HandlerInfo {
+            handler: self.to_string(),
+            file: entry.path,
+            exec: entry.exec,
+            terminal: entry.terminal,
+            working_dir: entry.working_dir,
+            actions: entry.actions.into_iter().map(|a| a.id).collect(),
+            mime_types: entry.mime_types,
+        })
+    }
+
+    /// Run the handler's Exec line, or that of one of its actions, with
+    /// `args` as its files/URLs
+    pub fn launch(
+        &self,
+        args: Vec<String>,
+        action: Option<&str>,
+    ) -> Result<()> {
+        let entry = self.get_entry()?;
+        match action {
+            Some(action) => entry.with_action(action)?.exec(&args),
+            None => entry.exec(&args),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn info() -> Result<()> {
+        std::env::set_var(
+            "XDG_DATA_HOME",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests"),
+        );
+        let info = "nvim.desktop".parse::<Handler>()?.info()?;
+
+        assert_eq!(
+            info.file,
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/applications/nvim.desktop")
+        );
+        assert_eq!(info.exec, "nvim %F");
+        assert!(info.terminal);
+        assert_eq!(info.working_dir, None);
+        assert_eq!(info.mime_types, ["text/plain", "text/markdown"]);
+        assert!(info.to_string().ends_with(
+            "\nExec=nvim %F\nTerminal=true\nMimeType=text/plain;text/markdown"
+        ));
+
+        let info = HandlerInfo {
+            actions: vec!["new-window".into(), "new-private-window".into()],
+            ..info
+        };
+        assert!(info
+            .to_string()
+            .contains("\nActions=new-window;new-private-window\n"));
+        Ok(())
+    }
 }
@@ -1,7 +1,7 @@
 use crate::{Error, Result};
 use mime::Mime;
 use std::convert::TryFrom;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use url::Url;
 
@@ -9,13 +9,76 @@ use url::Url;
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct MimeType(pub Mime);
 
+/// Whether a shared MIME database is installed. Without one, SharedMimeInfo
+/// silently knows no types, aliases or globs.
+pub fn mime_db_present() -> bool {
+    has_mime_db(data_dirs())
+}
+
+/// Data directories that may hold a shared MIME database, most important first
+fn data_dirs() -> Vec<PathBuf> {
+    match xdg::BaseDirectories::new() {
+        Ok(dirs) => std::iter::once(dirs.get_data_home())
+            .chain(dirs.get_data_dirs())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Read the `<mime> <mime>` lines of a shared MIME database file like
+/// `subclasses`, across every data directory, most important first
+pub fn read_mime_db_pairs(name: &str) -> Vec<(Mime, Mime)> {
+    data_dirs()
+        .into_iter()
+        .filter_map(|dir| {
+            std::fs::read_to_string(dir.join("mime").join(name)).ok()
+        })
+        .flat_map(|raw| {
+            raw.lines()
+                .filter_map(|line| {
+                    let (a, b) = line.split_once(' ')?;
+                    Some((a.parse().ok()?, b.parse().ok()?))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Every type listed in the shared MIME database's `types` files
+pub fn read_mime_db_types() -> Vec<Mime> {
+    data_dirs()
+        .into_iter()
+        .filter_map(|dir| std::fs::read_to_string(dir.join("mime/types")).ok())
+        .flat_map(|raw| {
+            raw.lines()
+                .filter_map(|line| line.parse().ok())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn has_mime_db(data_dirs: impl IntoIterator<Item = PathBuf>) -> bool {
+    data_dirs.into_iter().any(|dir| {
+        dir.join("mime/globs2").exists() || dir.join("mime/globs").exists()
+    })
+}
+
+/// Explain a failed detection, which may be down to a missing database
+fn ambiguous(path: &Path) -> Error {
+    if mime_db_present() {
+        Error::Ambiguous(path.to_owned())
+    } else {
+        Error::NoMimeDatabase
+    }
+}
+
 impl MimeType {
     fn from_ext(ext: &str) -> Result<Mime> {
         match &*xdg_mime::SharedMimeInfo::new()
             .get_mime_types_from_file_name(ext)
         {
             [m] if m == &mime::APPLICATION_OCTET_STREAM => {
-                Err(Error::Ambiguous(ext.into()))
+                Err(ambiguous(Path::new(ext)))
             }
             [guess, ..] => Ok(guess.clone()),
             [] => unreachable!(),
@@ -25,6 +88,14 @@ impl MimeType {
 
 impl From<&Url> for MimeType {
     fn from(url: &Url) -> Self {
+        if url.scheme() == "data" {
+            if let Some(mime) = data_url_mime(url) {
+                return Self(mime);
+            }
+        }
+
+        // Url lowercases the scheme, and every character a scheme may
+        // contain (alphanumerics, "+", "-", ".") is valid in a MIME subtype.
         Self(
             format!("x-scheme-handler/{}", url.scheme())
                 .parse::<Mime>()
@@ -33,19 +104,107 @@ impl From<&Url> for MimeType {
     }
 }
 
+// data: URLs embed their own media type, `data:[<mediatype>][;base64],<data>`
+// (RFC 2397). An omitted media type means text/plain.
+fn data_url_mime(url: &Url) -> Option<Mime> {
+    let (media_type, _data) = url.path().split_once(',')?;
+    let media_type = media_type.trim_end_matches(";base64");
+
+    if media_type.is_empty() || media_type.starts_with(';') {
+        return Some(mime::TEXT_PLAIN);
+    }
+
+    let mime = Mime::from_str(media_type).ok()?;
+    Mime::from_str(mime.essence_str()).ok()
+}
+
 impl TryFrom<&Path> for MimeType {
     type Error = Error;
     fn try_from(path: &Path) -> Result<Self> {
+        if let Some(mime) = inode_mime(path) {
+            return Ok(Self(mime.parse().unwrap()));
+        }
+
         let db = xdg_mime::SharedMimeInfo::new();
-        let guess = db.guess_mime_type().path(&path).guess();
+        let guess = db.guess_mime_type().path(path).guess();
+        let mut mime = guess.mime_type().clone();
 
-        let mime = mime_to_option(guess.mime_type().clone())
-            .ok_or_else(|| Error::Ambiguous(path.to_owned()))?;
+        // the guess trusts a file name with a single match without looking
+        // inside, so check the contents of files that exist
+        if path.is_file() {
+            if let Some(sniffed) = sniff_conflict(&db, path, &mime) {
+                mime = sniffed;
+            }
+        }
 
+        let mime = mime_to_option(mime).ok_or_else(|| ambiguous(path))?;
         Ok(Self(mime))
     }
 }
 
+/// The `inode/*` type of anything that isn't a regular file, which has no
+/// name or contents to go by. Reading a FIFO to sniff it would block.
+fn inode_mime(path: &Path) -> Option<&'static str> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let file_type = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.file_type(),
+        // a symlink that points nowhere
+        Err(_) if path.symlink_metadata().is_ok() => {
+            return Some("inode/symlink")
+        }
+        Err(_) => return None,
+    };
+
+    if file_type.is_dir() {
+        Some("inode/directory")
+    } else if file_type.is_block_device() {
+        Some("inode/blockdevice")
+    } else if file_type.is_char_device() {
+        Some("inode/chardevice")
+    } else if file_type.is_fifo() {
+        Some("inode/fifo")
+    } else if file_type.is_socket() {
+        Some("inode/socket")
+    } else {
+        None
+    }
+}
+
+/// How much of a file to read for magic, enough for every common rule
+const SNIFF_LEN: u64 = 16 * 1024;
+
+/// The type of a file's contents when it disagrees with the type from its
+/// name, like a shell script saved as .txt
+fn sniff_conflict(
+    db: &xdg_mime::SharedMimeInfo,
+    path: &Path,
+    name_mime: &Mime,
+) -> Option<Mime> {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    std::fs::File::open(path)
+        .ok()?
+        .take(SNIFF_LEN)
+        .read_to_end(&mut data)
+        .ok()?;
+    let (sniffed, priority) = db.get_mime_type_for_data(&data)?;
+
+    // weak rules like "<!--" for HTML are too easily matched by accident,
+    // and desktop files are never sniffed, following GIO, since opening
+    // one runs whatever it says
+    let weak = priority < 50
+        || sniffed == mime::APPLICATION_OCTET_STREAM
+        || ["application/x-zerosize", "application/x-desktop"]
+            .contains(&sniffed.essence_str());
+    // the name may be more specific, like a .docx over plain zip data
+    if weak || db.mime_type_subclass(name_mime, &sniffed) {
+        return None;
+    }
+    Some(sniffed)
+}
+
 fn mime_to_option(mime: Mime) -> Option<Mime> {
     if mime == mime::APPLICATION_OCTET_STREAM {
         None
@@ -54,20 +213,48 @@ fn mime_to_option(mime: Mime) -> Option<Mime> {
     }
 }
 
-// Mime derived from user input: extension(.pdf) or type like image/jpg
+// Mime derived from user input: extension(.pdf or pdf) or type like image/jpg
 #[derive(Debug)]
 pub struct MimeOrExtension(pub Mime);
 
+impl MimeOrExtension {
+    /// Parse user input, as a file name glob like `*.md` if `glob`
+    pub fn parse(s: &str, glob: bool) -> Result<Self> {
+        if glob {
+            Self::from_glob(s)
+        } else {
+            s.parse()
+        }
+    }
+
+    /// The mime of files matching `glob`, which is looked up as if it
+    /// were a file name itself, so `*.md` matches the `*.md` pattern
+    pub fn from_glob(glob: &str) -> Result<Self> {
+        let guesses = xdg_mime::SharedMimeInfo::new()
+            .get_mime_types_from_file_name(glob.trim());
+        match guesses.into_iter().next() {
+            Some(mime) if mime != mime::APPLICATION_OCTET_STREAM => {
+                Ok(Self(mime))
+            }
+            _ => Err(Error::UnknownGlob(glob.to_owned())),
+        }
+    }
+}
+
 impl FromStr for MimeOrExtension {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self> {
-        let mime = if s.starts_with(".") {
-            MimeType::from_ext(s)?
-        } else {
+        // Mime::from_str already lowercases the type and subtype,
+        // but rejects the stray whitespace that comes with pasted input
+        let s = s.trim();
+        let mime = if s.contains('/') {
             match Mime::from_str(s)? {
                 m if m.subtype() == "" => return Err(Error::InvalidMime(m)),
                 proper_mime => proper_mime,
             }
+        } else {
+            let ext = s.strip_prefix('.').unwrap_or(s);
+            MimeType::from_ext(&format!(".{}", ext))?
         };
 
         Ok(Self(mime))
@@ -92,6 +279,21 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn user_input_normalized() -> Result<()> {
+        assert_eq!("Image/PNG ".parse::<MimeOrExtension>()?.0, mime::IMAGE_PNG);
+        assert_eq!("  image/png".parse::<MimeOrExtension>()?.0, "image/png");
+        assert_eq!("TEXT/Html".parse::<MimeOrExtension>()?.0, mime::TEXT_HTML);
+        assert_eq!(" .PDF\n".parse::<MimeOrExtension>()?.0, "application/pdf");
+
+        let with_param =
+            "Text/Plain; charset=UTF-8".parse::<MimeOrExtension>()?.0;
+        assert_eq!(with_param.essence_str(), "text/plain");
+        assert_eq!(with_param.get_param(mime::CHARSET), Some(mime::UTF_8));
+
+        Ok(())
+    }
+
     #[test]
     fn from_path() -> Result<()> {
         assert_eq!(
@@ -117,6 +319,105 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn inodes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mime_of =
+            |path: &Path| -> Result<Mime> { Ok(MimeType::try_from(path)?.0) };
+
+        let folder = dir.path().join("folder.txt");
+        std::fs::create_dir(&folder)?;
+        assert_eq!(mime_of(&folder)?, "inode/directory");
+
+        let fifo = dir.path().join("fifo");
+        nix::unistd::mkfifo(&fifo, nix::sys::stat::Mode::S_IRWXU).unwrap();
+        assert_eq!(mime_of(&fifo)?, "inode/fifo");
+
+        let broken = dir.path().join("broken");
+        std::os::unix::fs::symlink(dir.path().join("nowhere"), &broken)?;
+        assert_eq!(mime_of(&broken)?, "inode/symlink");
+
+        // links to directories are followed
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&folder, &link)?;
+        assert_eq!(mime_of(&link)?, "inode/directory");
+
+        assert_eq!(mime_of(Path::new("/dev/null"))?, "inode/chardevice");
+        Ok(())
+    }
+
+    #[test]
+    fn content_over_name() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let detect = |name: &str, contents: &str| -> Result<Mime> {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents)?;
+            Ok(MimeType::try_from(path.as_path())?.0)
+        };
+
+        assert_eq!(
+            detect("script.txt", "#!/bin/sh\necho hi\n")?,
+            "application/x-shellscript"
+        );
+        // weak magic doesn't override the name
+        assert_eq!(
+            detect("notes.md", "<!-- draft -->\n# Notes\n")?,
+            "text/markdown"
+        );
+        assert_eq!(detect("notes.txt", "just text\n")?, "text/plain");
+        // nonexistent files only go by name
+        assert_eq!(
+            MimeType::try_from(dir.path().join("missing.txt").as_path())?.0,
+            "text/plain"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn from_data_url() {
+        let mime_of = |s: &str| MimeType::from(&Url::parse(s).unwrap()).0;
+
+        assert_eq!(mime_of("data:image/png;base64,iVBORw0KGgo="), "image/png");
+        assert_eq!(
+            mime_of("data:text/html;charset=utf-8,<p>hello</p>"),
+            "text/html"
+        );
+        assert_eq!(mime_of("data:,Hello%2C%20World!"), "text/plain");
+        assert_eq!(mime_of("data:;base64,SGVsbG8="), "text/plain");
+        assert_eq!(mime_of("data:not-a-mime,foo"), "x-scheme-handler/data");
+        assert_eq!(mime_of("https://example.com"), "x-scheme-handler/https");
+    }
+
+    #[test]
+    fn from_scheme() -> Result<()> {
+        for (url, mime) in [
+            ("web+ap://example.com/@user", "x-scheme-handler/web+ap"),
+            ("x-foo.bar:baz", "x-scheme-handler/x-foo.bar"),
+            ("WEB+AP:thing", "x-scheme-handler/web+ap"),
+            ("https://example.com", "x-scheme-handler/https"),
+        ] {
+            let from_url = MimeType::from(&Url::parse(url).unwrap()).0;
+            assert_eq!(from_url, mime);
+            assert_eq!(from_url, mime.parse::<MimeOrExtension>()?.0);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_mime_db() {
+        let empty = tempfile::tempdir().unwrap();
+        assert!(!has_mime_db(vec![empty.path().to_owned()]));
+
+        let db = tempfile::tempdir().unwrap();
+        std::fs::create_dir(db.path().join("mime")).unwrap();
+        std::fs::write(db.path().join("mime/globs2"), "").unwrap();
+        assert!(has_mime_db(vec![
+            empty.path().to_owned(),
+            db.path().to_owned()
+        ]));
+    }
+
     #[test]
     fn from_ext() -> Result<()> {
         assert_eq!(".mp3".parse::<MimeOrExtension>()?.0, "audio/mpeg");
@@ -126,4 +427,31 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn from_glob() -> Result<()> {
+        assert_eq!(MimeOrExtension::from_glob("*.md")?.0, "text/markdown");
+        assert_eq!(MimeOrExtension::from_glob("*.jpe")?.0, mime::IMAGE_JPEG);
+        MimeOrExtension::from_glob("*").unwrap_err();
+        assert_eq!(MimeOrExtension::parse("*.jpeg", true)?.0, mime::IMAGE_JPEG);
+        assert!(matches!(
+            MimeOrExtension::from_glob("*.handlr-nonexistent"),
+            Err(Error::UnknownGlob(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn ext_without_dot() -> Result<()> {
+        for input in ["pdf", ".pdf", "PDF", "application/pdf"] {
+            assert_eq!(
+                input.parse::<MimeOrExtension>()?.0,
+                mime::APPLICATION_PDF,
+                "{}",
+                input
+            );
+        }
+        "".parse::<MimeOrExtension>().unwrap_err();
+        Ok(())
+    }
 }
@@ -1,8 +1,12 @@
 pub mod atomic_save;
+mod desktop_entry;
 mod handler;
 mod mime_types;
 mod path;
 
+pub use desktop_entry::DesktopEntry;
 pub use handler::Handler;
-pub use mime_types::{MimeOrExtension, MimeType};
+pub use mime_types::{
+    mime_db_present, read_mime_db_pairs, read_mime_db_types, MimeOrExtension,
+};
 pub use path::UserPath;
@@ -1,6 +1,10 @@
+use mime::Mime;
+use serde::Serialize;
 use url::Url;
 
+use super::mime_types::MimeType;
 use crate::{Error, Result};
+use std::convert::TryFrom;
 use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -13,7 +17,7 @@ pub enum UserPath {
 impl FromStr for UserPath {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let normalized = match url::Url::parse(&s) {
+        let normalized = match url::Url::parse(s) {
             Ok(url) if url.scheme() == "file" => {
                 let path = url
                     .to_file_path()
@@ -32,8 +36,70 @@ impl FromStr for UserPath {
 impl Display for UserPath {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
         match self {
-            Self::File(f) => fmt.write_str(&f.to_string_lossy().to_string()),
-            Self::Url(u) => fmt.write_str(&u.to_string()),
+            Self::File(f) => fmt.write_str(&f.to_string_lossy()),
+            Self::Url(u) => fmt.write_str(u.as_str()),
         }
     }
 }
+
+/// A path's mime, as reported by `handlr mime`
+#[derive(Serialize)]
+pub struct DetectedMime {
+    pub path: String,
+    pub mime: String,
+    /// The file doesn't exist, so only its name was looked at
+    pub guess: bool,
+}
+
+impl UserPath {
+    /// The mime to look up handlers for: `x-scheme-handler/<scheme>` for
+    /// URLs, and the sniffed type for files
+    pub fn mime_type(&self) -> Result<Mime> {
+        Ok(match self {
+            Self::Url(url) => MimeType::from(url).0,
+            Self::File(f) => MimeType::try_from(f.as_path())?.0,
+        })
+    }
+
+    pub fn detect_mime(&self) -> Result<DetectedMime> {
+        Ok(DetectedMime {
+            path: self.to_string(),
+            mime: self.mime_type()?.to_string(),
+            guess: matches!(self, Self::File(f) if !f.exists()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_schemes() -> Result<()> {
+        let mime = |s: &str| s.parse::<UserPath>()?.mime_type();
+        assert_eq!(mime("mailto:me@example.com")?, "x-scheme-handler/mailto");
+        assert_eq!(mime("HTTPS://example.com")?, "x-scheme-handler/https");
+        assert_eq!(mime("Git+SSH://host/repo")?, "x-scheme-handler/git+ssh");
+        Ok(())
+    }
+
+    #[test]
+    fn detect_mime() -> Result<()> {
+        let detect = |s: &str| s.parse::<UserPath>()?.detect_mime();
+
+        let file = detect("./tests/rust.vim")?;
+        assert_eq!((file.mime.as_str(), file.guess), ("text/plain", false));
+
+        let missing = detect("./tests/missing.pdf")?;
+        assert_eq!(
+            (missing.mime.as_str(), missing.guess),
+            ("application/pdf", true)
+        );
+
+        let url = detect("https://example.com")?;
+        assert_eq!(url.mime, "x-scheme-handler/https");
+        assert!(!url.guess);
+
+        Ok(())
+    }
+}
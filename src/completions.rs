@@ -0,0 +1,551 @@
+//! Completion scripts generated from the clap `App`, so they list the same
+//! subcommands and flags as `--help`. Mimes and desktop files are completed
+//! as you type by calling the hidden `handlr autocomplete` command.
+
+use crate::cli::Shell;
+use clap::{App, AppSettings, Arg, ArgSettings, ValueHint};
+use itertools::Itertools;
+
+/// What a flag's value or a positional argument completes to
+enum Value {
+    /// The mimes in mimeapps.list, from `handlr autocomplete -m`
+    Mimes,
+    /// Installed desktop files, from `handlr autocomplete -d`
+    Desktops,
+    Files,
+    Choices(Vec<String>),
+    /// Anything, like a desktop action, so nothing is offered
+    Text,
+}
+
+impl Value {
+    fn of(arg: &Arg) -> Self {
+        if let Some(values) = arg.get_possible_values() {
+            return Value::Choices(values.iter().map(|&v| v.into()).collect());
+        }
+        match arg.get_name() {
+            "mime" | "mimes" => Value::Mimes,
+            "handler" | "with" => Value::Desktops,
+            _ => match arg.get_value_hint() {
+                ValueHint::AnyPath
+                | ValueHint::FilePath
+                | ValueHint::DirPath => Value::Files,
+                _ => Value::Text,
+            },
+        }
+    }
+}
+
+struct Flag {
+    name: String,
+    short: Option<char>,
+    long: Option<String>,
+    about: String,
+    /// What its value completes to, if it takes one
+    value: Option<Value>,
+    multiple: bool,
+}
+
+impl Flag {
+    /// How it's written on the command line, short first
+    fn spellings(&self) -> Vec<String> {
+        let short = self.short.map(|s| format!("-{}", s));
+        let long = self.long.as_ref().map(|l| format!("--{}", l));
+        short.into_iter().chain(long).collect()
+    }
+}
+
+struct Positional {
+    name: String,
+    value: Value,
+    multiple: bool,
+}
+
+struct Command {
+    /// The name, then any aliases
+    names: Vec<String>,
+    about: String,
+    flags: Vec<Flag>,
+    /// Nested subcommands are completed as the first positional argument
+    positionals: Vec<Positional>,
+}
+
+impl Command {
+    fn new(app: &App) -> Self {
+        let mut flags = Vec::new();
+        let mut positionals = Vec::new();
+        let nested =
+            visible(app).map(|cmd| cmd.get_name().into()).collect_vec();
+        if !nested.is_empty() {
+            positionals.push(Positional {
+                name: "command".into(),
+                value: Value::Choices(nested),
+                multiple: false,
+            });
+        }
+        for arg in app.get_arguments() {
+            let multiple = arg.is_set(ArgSettings::MultipleOccurrences);
+            if arg.get_short().is_none() && arg.get_long().is_none() {
+                positionals.push(Positional {
+                    name: arg.get_name().into(),
+                    value: Value::of(arg),
+                    multiple,
+                });
+            } else {
+                flags.push(Flag {
+                    name: arg.get_name().into(),
+                    short: arg.get_short(),
+                    long: arg.get_long().map(Into::into),
+                    about: about(arg.get_about()),
+                    value: Some(Value::of(arg))
+                        .filter(|_| arg.is_set(ArgSettings::TakesValue)),
+                    multiple,
+                });
+            }
+        }
+
+        Command {
+            names: std::iter::once(app.get_name())
+                .chain(app.get_all_aliases())
+                .map(Into::into)
+                .collect(),
+            about: about(app.get_about()),
+            flags,
+            positionals,
+        }
+    }
+}
+
+/// The subcommands of `app` that aren't hidden
+fn visible<'a, 'help>(
+    app: &'a App<'help>,
+) -> impl Iterator<Item = &'a App<'help>> {
+    app.get_subcommands()
+        .filter(|cmd| !cmd.is_set(AppSettings::Hidden))
+}
+
+/// The first sentence of a doc comment, as shells show them
+fn about(about: Option<&str>) -> String {
+    let about = about.unwrap_or_default();
+    about
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('.')
+        .into()
+}
+
+pub fn generate(shell: &Shell, app: &App) -> String {
+    let globals = Command::new(app).flags;
+    let commands = visible(app).map(Command::new).collect_vec();
+    let valued = globals
+        .iter()
+        .chain(commands.iter().flat_map(|cmd| &cmd.flags))
+        .filter(|flag| flag.value.is_some())
+        .collect_vec();
+    match shell {
+        Shell::Bash => bash(&globals, &commands, &valued),
+        Shell::Zsh => zsh(&globals, &commands),
+        Shell::Fish => fish(&globals, &commands, &valued),
+    }
+}
+
+fn bash(globals: &[Flag], commands: &[Command], valued: &[&Flag]) -> String {
+    fn complete(value: &Value) -> Option<String> {
+        Some(match value {
+            Value::Mimes => "_handlr_mimes \"$cur\"".into(),
+            Value::Desktops => "_handlr_desktops \"$cur\"".into(),
+            Value::Files => "COMPREPLY=($(compgen -f -- \"$cur\"))".into(),
+            Value::Choices(choices) => format!(
+                "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+                choices.join(" ")
+            ),
+            Value::Text => return None,
+        })
+    }
+    let spellings =
+        |flags: &[Flag]| flags.iter().flat_map(Flag::spellings).join(" ");
+    let global_flags = spellings(globals);
+
+    let mut out = String::from(
+        r#"_handlr_mimes() {
+  COMPREPLY=($(compgen -W "$(handlr autocomplete -m)" -- "$1"))
+}
+
+_handlr_desktops() {
+  COMPREPLY=($(compgen -W "$(handlr autocomplete -d | cut -f1)" -- "$1"))
+}
+
+_handlr() {
+  local cur=${COMP_WORDS[COMP_CWORD]}
+  local prev=${COMP_WORDS[COMP_CWORD-1]}
+
+  # the subcommand, and how many of its positional arguments come before
+  # the word being completed
+  local i cmd arg=0
+  for ((i = 1; i < COMP_CWORD; i++)); do
+    case ${COMP_WORDS[i]} in
+"#,
+    );
+    out += &format!(
+        "      {}) ((i++)) ;;\n",
+        valued.iter().flat_map(|f| f.spellings()).unique().join("|")
+    );
+    out += r#"      -*) ;;
+      *)
+        if [[ -z $cmd ]]; then
+          cmd=${COMP_WORDS[i]}
+        else
+          ((arg++))
+        fi
+        ;;
+    esac
+  done
+
+  case $prev in
+"#;
+    // flags completing the same way share an arm
+    let mut arms: Vec<(Option<String>, Vec<String>)> = Vec::new();
+    for flag in valued {
+        let action = flag.value.as_ref().and_then(complete);
+        match arms.iter_mut().find(|(a, _)| *a == action) {
+            Some((_, spellings)) => spellings.extend(flag.spellings()),
+            None => arms.push((action, flag.spellings())),
+        }
+    }
+    for (action, spellings) in arms {
+        let spellings = spellings.into_iter().unique().join("|");
+        match action {
+            Some(action) => {
+                out += &format!(
+                    "    {})\n      {}\n      return\n",
+                    spellings, action
+                );
+                out += "      ;;\n";
+            }
+            None => out += &format!("    {}) return ;;\n", spellings),
+        }
+    }
+
+    out += &format!(
+        r#"  esac
+
+  if [[ -z $cmd ]]; then
+    if [[ $cur == -* ]]; then
+      COMPREPLY=($(compgen -W "{}" -- "$cur"))
+    else
+      COMPREPLY=($(compgen -W "{}" -- "$cur"))
+    fi
+    return
+  fi
+
+  case $cmd in
+"#,
+        global_flags,
+        commands.iter().flat_map(|cmd| &cmd.names).join(" ")
+    );
+    for cmd in commands {
+        let flags = [spellings(&cmd.flags), global_flags.clone()];
+        out += &format!("    {})\n", cmd.names.join("|"));
+        out += "      if [[ $cur == -* ]]; then\n";
+        out += &format!(
+            "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n",
+            flags.iter().filter(|f| !f.is_empty()).join(" ")
+        );
+        out += "        return\n      fi\n";
+        let arms = cmd
+            .positionals
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| {
+                let index = match p.multiple {
+                    true => "*".into(),
+                    false => i.to_string(),
+                };
+                Some((index, complete(&p.value)?))
+            })
+            .collect_vec();
+        if !arms.is_empty() {
+            out += "      case $arg in\n";
+            for (index, action) in arms {
+                out += &format!("        {}) {} ;;\n", index, action);
+            }
+            out += "      esac\n";
+        }
+        out += "      ;;\n";
+    }
+    out += "  esac\n}\n\ncomplete -F _handlr handlr\n";
+    out
+}
+
+fn zsh(globals: &[Flag], commands: &[Command]) -> String {
+    /// Quote for a single-quoted zsh string, escaping `[` and `]` as
+    /// _arguments needs in descriptions
+    fn quote(s: &str) -> String {
+        s.replace('\'', r"'\''")
+            .replace('[', r"\[")
+            .replace(']', r"\]")
+    }
+    fn action(value: &Value) -> String {
+        match value {
+            Value::Mimes => "_handlr_mimes".into(),
+            Value::Desktops => "_handlr_desktops".into(),
+            Value::Files => "_files".into(),
+            Value::Choices(choices) => format!("({})", choices.join(" ")),
+            Value::Text => " ".into(),
+        }
+    }
+    fn flag_spec(flag: &Flag) -> String {
+        let value = match &flag.value {
+            Some(value) => format!(":{}:{}", flag.name, action(value)),
+            None => String::new(),
+        };
+        let describe = match flag.about.as_str() {
+            "" => value,
+            about => format!("[{}]{}", quote(about), value),
+        };
+        match (flag.spellings().as_slice(), flag.multiple) {
+            ([spelling], true) => format!("'*{}{}'", spelling, describe),
+            ([spelling], false) => format!("'{}{}'", spelling, describe),
+            (spellings, multiple) => format!(
+                "'{}'{{{}}}'{}'",
+                match multiple {
+                    true => "*".into(),
+                    false => format!("({})", spellings.join(" ")),
+                },
+                spellings.join(","),
+                describe
+            ),
+        }
+    }
+
+    let mut out = String::from(
+        r#"#compdef handlr
+
+_handlr_mimes() {
+  local -a mimes
+  mimes=(${(f)"$(handlr autocomplete -m)"})
+  _describe -t mimes 'mime' mimes
+}
+
+_handlr_desktops() {
+  local -a desktops
+  desktops=(${(f)"$(handlr autocomplete -d | tr '\t' ':')"})
+  _describe -t desktops 'desktop file' desktops
+}
+
+_handlr() {
+  local curcontext="$curcontext" state line
+  typeset -A opt_args
+  local -a globals
+  globals=(
+"#,
+    );
+    for flag in globals {
+        out += &format!("    {}\n", flag_spec(flag));
+    }
+    out += r#"  )
+
+  _arguments -C $globals \
+    '1: :->command' \
+    '*:: :->arguments'
+
+  case $state in
+    (command)
+      local -a commands
+      commands=(
+"#;
+    for cmd in commands {
+        for name in &cmd.names {
+            let about = cmd.about.replace(':', r"\:").replace('\'', r"'\''");
+            out += &format!("        '{}:{}'\n", name, about);
+        }
+    }
+    out += r#"      )
+      _describe -t commands 'command' commands
+      ;;
+    (arguments)
+      curcontext=${curcontext%:*:*}:handlr-$words[1]:
+      case $words[1] in
+"#;
+    for cmd in commands {
+        let specs = cmd
+            .flags
+            .iter()
+            .map(flag_spec)
+            .chain(cmd.positionals.iter().enumerate().map(|(i, p)| {
+                let index = match p.multiple {
+                    true => "*".into(),
+                    false => (i + 1).to_string(),
+                };
+                format!("'{}:{}:{}'", index, p.name, action(&p.value))
+            }))
+            .collect_vec();
+        out += &format!("        ({})\n", cmd.names.join("|"));
+        out += "          _arguments $globals";
+        for spec in specs {
+            out += &format!(" \\\n            {}", spec);
+        }
+        out += "\n          ;;\n";
+    }
+    out += "      esac\n      ;;\n  esac\n}\n\n_handlr \"$@\"\n";
+    out
+}
+
+fn fish(globals: &[Flag], commands: &[Command], valued: &[&Flag]) -> String {
+    fn quote(s: &str) -> String {
+        format!("'{}'", s.replace('\\', r"\\").replace('\'', r"\'"))
+    }
+    fn complete(value: &Value) -> String {
+        match value {
+            Value::Mimes => " -a '(handlr autocomplete -m)'".into(),
+            Value::Desktops => " -a '(handlr autocomplete -d)'".into(),
+            Value::Files => " -F".into(),
+            Value::Choices(choices) => {
+                format!(" -a {}", quote(&choices.join(" ")))
+            }
+            Value::Text => String::new(),
+        }
+    }
+    fn flag_line(condition: Option<&str>, flag: &Flag) -> String {
+        let mut line = String::from("complete -c handlr");
+        if let Some(condition) = condition {
+            line += &format!(" -n {}", quote(condition));
+        }
+        if let Some(short) = flag.short {
+            line += &format!(" -s {}", short);
+        }
+        if let Some(long) = &flag.long {
+            line += &format!(" -l {}", long);
+        }
+        if !flag.about.is_empty() {
+            line += &format!(" -d {}", quote(&flag.about));
+        }
+        if let Some(value) = &flag.value {
+            line += " -r";
+            line += &complete(value);
+        }
+        line + "\n"
+    }
+
+    let mut out = format!(
+        r#"# the positional arguments so far, without flags and their values
+function __handlr_args
+    set -l words (commandline -opc)
+    set -e words[1]
+    while set -q words[1]
+        switch $words[1]
+            case {}
+                set -e words[1]
+            case '-*'
+            case '*'
+                echo $words[1]
+        end
+        set -e words[1]
+    end
+end
+
+function __handlr_no_command
+    set -l args (__handlr_args)
+    not set -q args[1]
+end
+
+# whether the subcommand is one of the comma-separated `names`
+function __handlr_command -a names
+    set -l args (__handlr_args)
+    contains -- "$args[1]" (string split , -- $names)
+end
+
+# whether the argument being typed is the subcommand's positional argument
+# at `index`, or any after it with `multiple`
+function __handlr_arg -a names index multiple
+    __handlr_command $names; or return
+    set -l typed (math (count (__handlr_args)) - 1)
+    test $typed -eq $index; and return
+    test -n "$multiple"; and test $typed -gt $index
+end
+
+complete -c handlr -f
+"#,
+        valued.iter().flat_map(|f| f.spellings()).unique().join(" ")
+    );
+    for cmd in commands {
+        for name in &cmd.names {
+            out += &format!(
+                "complete -c handlr -n __handlr_no_command -a {} -d {}\n",
+                name,
+                quote(&cmd.about)
+            );
+        }
+    }
+    for flag in globals {
+        out += &flag_line(None, flag);
+    }
+    for cmd in commands {
+        let names = cmd.names.join(",");
+        let condition = format!("__handlr_command {}", names);
+        for flag in &cmd.flags {
+            out += &flag_line(Some(&condition), flag);
+        }
+        for (i, p) in cmd.positionals.iter().enumerate() {
+            let multiple = if p.multiple { " multiple" } else { "" };
+            let condition = format!("__handlr_arg {} {}{}", names, i, multiple);
+            let complete = complete(&p.value);
+            if !complete.is_empty() {
+                out += &format!(
+                    "complete -c handlr -n {}{}\n",
+                    quote(&condition),
+                    complete
+                );
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use clap::IntoApp;
+
+    #[test]
+    fn shipped_scripts_are_current() {
+        let shipped = [
+            ("bash", include_str!("../completions/handlr.bash")),
+            ("zsh", include_str!("../completions/_handlr")),
+            ("fish", include_str!("../completions/handlr.fish")),
+        ];
+        for (name, script) in shipped.iter() {
+            let shell = name.parse::<Shell>().unwrap();
+            assert!(
+                shell.script() == *script,
+                "completions/ is stale, regenerate it with \
+                 `handlr completions {}`",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn dynamic_values() {
+        let set = Command::new(
+            Cli::into_app()
+                .get_subcommands()
+                .find(|cmd| cmd.get_name() == "set")
+                .unwrap(),
+        );
+        assert!(matches!(set.positionals[0].value, Value::Mimes));
+        assert!(matches!(set.positionals[1].value, Value::Desktops));
+        assert!(matches!(set.flags[0].value, Some(Value::Mimes)));
+        // hidden commands aren't offered
+        let app = Cli::into_app();
+        assert!(visible(&app).all(|cmd| cmd.get_name() != "autocomplete"));
+
+        for name in &["bash", "zsh", "fish"] {
+            let script = name.parse::<Shell>().unwrap().script();
+            assert!(script.contains("handlr autocomplete -m"));
+            assert!(script.contains("handlr autocomplete -d"));
+        }
+    }
+}
@@ -1,20 +1,377 @@
-use once_cell::sync::Lazy;
+use crate::common::Handler;
+use crate::{utils, Error, Result};
+use once_cell::sync::{Lazy, OnceCell};
 use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
-pub static CONFIG: Lazy<Config> = Lazy::new(Config::load);
+pub static CONFIG: Lazy<Config> = Lazy::new(|| Config::load().unwrap());
 
-#[derive(Serialize, Deserialize)]
+/// Set by `--config`, taking precedence over $HANDLR_CONFIG
+pub static CONFIG_PATH: OnceCell<PathBuf> = OnceCell::new();
+
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(default)]
-pub struct Config {}
+pub struct Config {
+    /// Handlers shown per mime in `list` before truncating, 0 for no limit
+    pub max_handlers_display: usize,
+    /// Command to run Terminal=true entries in, invoked as `<terminal> -e`.
+    /// Unset, $TERMINAL is used, then x-terminal-emulator.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub terminal: Option<String>,
+    /// Pick among several handlers with `selector`, instead of the first
+    pub enable_selector: bool,
+    /// Command that reads options on stdin and prints the chosen one
+    pub selector: String,
+    /// Run the selector on the terminal handlr was started from, for
+    /// selectors like fzf rather than rofi or dmenu
+    pub selector_is_terminal: bool,
+    /// Show errors as desktop notifications when not run from a terminal
+    pub notify_on_error: bool,
+    /// Sync mimeapps.list's directory after saving, so a crash right after
+    /// can't lose the change, at the cost of slower writes
+    pub durable_writes: bool,
+}
 
 impl Default for Config {
     fn default() -> Self {
-        Config {}
+        Config {
+            max_handlers_display: 5,
+            terminal: None,
+            enable_selector: false,
+            selector: "rofi -dmenu -i -p 'Open With: '".into(),
+            selector_is_terminal: false,
+            notify_on_error: true,
+            durable_writes: false,
+        }
     }
 }
 
 impl Config {
-    pub fn load() -> Self {
-        confy::load("handlr").unwrap()
+    pub fn load() -> Result<Self> {
+        match Self::override_path() {
+            Some(path) => Ok(confy::load_path(path)?),
+            None => Ok(confy::load("handlr")?),
+        }
+    }
+
+    pub fn path() -> Result<PathBuf> {
+        match Self::override_path() {
+            Some(path) => Ok(path),
+            None => Ok(xdg::BaseDirectories::with_prefix("handlr")?
+                .get_config_home()
+                .join("handlr.toml")),
+        }
+    }
+
+    /// The file picked with `--config` or $HANDLR_CONFIG, if any
+    fn override_path() -> Option<PathBuf> {
+        CONFIG_PATH.get().cloned().or_else(|| {
+            std::env::var_os("HANDLR_CONFIG")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+        })
+    }
+
+    /// The effective config as TOML, with defaults filled in for unset fields
+    /// and the terminal that would be launched
+    pub fn dump(&self) -> Result<String> {
+        let effective = Config {
+            terminal: Some(self.terminal()),
+            ..self.clone()
+        };
+        Ok(toml::to_string(&effective)
+            .map_err(confy::ConfyError::SerializeTomlError)?)
+    }
+
+    /// Check that the config at `path` deserializes, without creating it
+    pub fn check(path: &Path) -> Result<()> {
+        if path.exists() {
+            confy::load_path::<Self>(path)?;
+        }
+        Ok(())
+    }
+
+    /// Whether an error should also pop up as a notification, since
+    /// nobody may be watching stderr without a terminal
+    pub fn notifies_errors(&self, tty: bool) -> bool {
+        !tty && self.notify_on_error
+    }
+
+    /// `terminal`, falling back to $TERMINAL and then x-terminal-emulator
+    fn terminal(&self) -> String {
+        match &self.terminal {
+            Some(terminal) => terminal.clone(),
+            None => std::env::var("TERMINAL")
+                .ok()
+                .filter(|terminal| !terminal.is_empty())
+                .unwrap_or_else(|| "x-terminal-emulator".into()),
+        }
+    }
+
+    /// The terminal command split into words, checked to exist
+    pub fn terminal_cmd(&self) -> Result<Vec<String>> {
+        let terminal = self.terminal();
+        let split = shlex::split(&terminal)
+            .filter(|split| !split.is_empty())
+            .ok_or_else(|| Error::BadCmd(terminal.clone()))?;
+        if utils::find_in_path(&split[0]).is_none() {
+            return Err(Error::TerminalNotFound(split[0].clone()));
+        }
+        Ok(split)
+    }
+
+    /// Ask the user to pick one of `opts` with the selector. Without a
+    /// terminal to report a broken selector to, the first option is used.
+    pub fn select(&self, opts: impl Iterator<Item = String>) -> Result<String> {
+        let opts = opts.collect::<Vec<_>>();
+
+        let selected = match self.run_selector(&opts) {
+            Err(
+                e @ (Error::SelectorUnavailable(..)
+                | Error::SelectorNeedsTerminal(_)),
+            ) if !atty::is(atty::Stream::Stdout) => {
+                eprintln!("warning: {}, using the first handler", e);
+                return opts.into_iter().next().ok_or(Error::Cancelled);
+            }
+            selected => selected?,
+        };
+
+        if selected.is_empty() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(selected)
+        }
+    }
+
+    /// Let the user pick one of `handlers` by application name, falling back
+    /// to the desktop file name for entries without one
+    pub fn select_handler(&self, handlers: Vec<Handler>) -> Result<Handler> {
+        let names = handlers
+            .iter()
+            .map(|h| h.name().unwrap_or_else(|| h.to_string()))
+            .collect::<Vec<_>>();
+        // two apps sharing a name are told apart by their file names
+        let labels = names
+            .iter()
+            .zip(&handlers)
+            .map(|(name, handler)| {
+                if names.iter().filter(|n| *n == name).count() > 1 {
+                    format!("{} ({})", name, handler)
+                } else {
+                    name.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let selected = self.select(labels.iter().cloned())?;
+        labels
+            .iter()
+            .position(|label| *label == selected)
+            .map(|i| handlers[i].clone())
+            .ok_or(Error::NotFound(selected, None))
+    }
+
+    fn selector_cmd(&self) -> Result<Command> {
+        let split = shlex::split(&self.selector)
+            .filter(|split| !split.is_empty())
+            .ok_or_else(|| Error::BadCmd(self.selector.clone()))?;
+        let mut cmd = Command::new(&split[0]);
+        cmd.args(&split[1..]);
+        Ok(cmd)
+    }
+
+    fn run_selector(&self, opts: &[String]) -> Result<String> {
+        let cmd = self.selector_cmd()?;
+        let output = if self.selector_is_terminal {
+            if std::fs::File::open("/dev/tty").is_err() {
+                return Err(Error::SelectorNeedsTerminal(
+                    self.selector.clone(),
+                ));
+            }
+            self.select_in_terminal(cmd, opts)?
+        } else {
+            self.select_piped(cmd, opts)?
+        };
+        Ok(output.trim().to_owned())
+    }
+
+    /// Pipe the options through the selector, for ones like rofi and
+    /// dmenu that open their own window
+    fn select_piped(
+        &self,
+        mut cmd: Command,
+        opts: &[String],
+    ) -> Result<String> {
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| self.unavailable(e))?;
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(opts.join("\n").as_bytes())?;
+        let output = child.wait_with_output()?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Hand the options to the selector in a file, leaving stderr on the
+    /// inherited terminal for ones like fzf that draw their UI there
+    fn select_in_terminal(
+        &self,
+        mut cmd: Command,
+        opts: &[String],
+    ) -> Result<String> {
+        let mut input = tempfile::tempfile()?;
+        input.write_all(opts.join("\n").as_bytes())?;
+        input.seek(SeekFrom::Start(0))?;
+        let mut output = tempfile::tempfile()?;
+
+        cmd.stdin(input)
+            .stdout(output.try_clone()?)
+            .status()
+            .map_err(|e| self.unavailable(e))?;
+
+        let mut selected = String::new();
+        output.seek(SeekFrom::Start(0))?;
+        output.read_to_string(&mut selected)?;
+        Ok(selected)
+    }
+
+    fn unavailable(&self, e: std::io::Error) -> Error {
+        let mode = if self.selector_is_terminal {
+            "fzf-style terminal"
+        } else {
+            "rofi/dmenu-style piped"
+        };
+        Error::SelectorUnavailable(self.selector.clone(), mode, e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn terminal_cmd() -> Result<()> {
+        let mut config = Config {
+            terminal: Some("sh -l".into()),
+            ..Default::default()
+        };
+        assert_eq!(config.terminal_cmd()?, vec!["sh", "-l"]);
+
+        config.terminal = Some("handlr-missing-terminal".into());
+        assert!(matches!(
+            config.terminal_cmd(),
+            Err(Error::TerminalNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn dump_lists_every_field() -> Result<()> {
+        let config = Config {
+            terminal: Some("foot".into()),
+            ..Default::default()
+        };
+        let dump = config.dump()?.parse::<toml::Value>().unwrap();
+        let fields = dump
+            .as_table()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            [
+                "durable_writes",
+                "enable_selector",
+                "max_handlers_display",
+                "notify_on_error",
+                "selector",
+                "selector_is_terminal",
+                "terminal",
+            ]
+        );
+        assert_eq!(dump["terminal"].as_str(), Some("foot"));
+
+        // an unset terminal shows the one launched instead
+        let dump = Config::default().dump()?;
+        assert!(dump.contains(&format!(
+            "terminal = {:?}",
+            Config::default().terminal()
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn notify_on_error() {
+        let mut config = Config::default();
+        assert!(config.notifies_errors(false));
+        assert!(!config.notifies_errors(true));
+
+        config.notify_on_error = false;
+        assert!(!config.notifies_errors(false));
+    }
+
+    #[test]
+    fn config_env() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("profile.toml");
+        std::fs::write(&path, "max_handlers_display = 2\n")?;
+
+        let _env = crate::utils::ENV_LOCK.lock().unwrap();
+        std::env::set_var("HANDLR_CONFIG", &path);
+        let (found, config) = (Config::path(), Config::load());
+        std::env::remove_var("HANDLR_CONFIG");
+
+        assert_eq!(found?, path);
+        assert_eq!(config?.max_handlers_display, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn select() -> Result<()> {
+        let mut config = Config {
+            selector: "tail -n 1".into(),
+            ..Default::default()
+        };
+        let opts = || vec!["a.desktop".into(), "b.desktop".into()].into_iter();
+        assert_eq!(config.select(opts())?, "b.desktop");
+
+        let opts = opts().collect::<Vec<_>>();
+        assert_eq!(
+            config.select_in_terminal(config.selector_cmd()?, &opts)?,
+            "b.desktop"
+        );
+
+        config.selector = "handlr-missing-selector -dmenu".into();
+        assert!(matches!(
+            config.run_selector(&opts),
+            Err(Error::SelectorUnavailable(..))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn select_handler() -> Result<()> {
+        std::env::set_var(
+            "XDG_DATA_HOME",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests"),
+        );
+        let config = Config {
+            selector: "grep -x Firefox".into(),
+            ..Default::default()
+        };
+        let firefox = "firefox.desktop".parse::<Handler>()?;
+        let nvim = "nvim.desktop".parse::<Handler>()?;
+        assert_eq!(
+            config.select_handler(vec![nvim, firefox.clone()])?,
+            firefox
+        );
+        Ok(())
     }
 }
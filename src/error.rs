@@ -1,23 +1,63 @@
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error(transparent)]
-    ParseApps(#[from] pest::error::Error<crate::apps::MimeappsRule>),
+    ParseApps(#[from] Box<pest::error::Error<crate::apps::MimeappsRule>>),
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
     Xdg(#[from] xdg::BaseDirectoriesError),
     #[error(transparent)]
     Config(#[from] confy::ConfyError),
-    #[error("no handlers found for '{0}'")]
-    NotFound(String),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(
+        "no handlers found for '{0}'{}",
+        .1.as_ref().map(|m| format!(", did you mean '{}'?", m)).unwrap_or_default()
+    )]
+    NotFound(String, Option<String>),
     #[error("could not figure out the mime type of '{0}'")]
     Ambiguous(std::path::PathBuf),
+    #[error("no shared MIME database found, please install shared-mime-info")]
+    NoMimeDatabase,
     #[error(transparent)]
     BadMimeType(#[from] mime::FromStrError),
     #[error("bad mime: {0}")]
     InvalidMime(mime::Mime),
+    #[error("no known mime type matches '{0}'")]
+    UnknownGlob(String),
     #[error("Bad path: {0}")]
     BadPath(String),
+    #[error("could not parse command: {0}")]
+    BadCmd(String),
+    #[error("could not read desktop entry '{}'", .0.display())]
+    BadEntry(std::path::PathBuf),
+    #[error("{0} has no action '{1}', it has: {2}")]
+    UnknownAction(String, String, String),
+    #[error(
+        "terminal '{0}' not found, set `terminal` in handlr.toml \
+         or $TERMINAL to run Terminal=true entries"
+    )]
+    TerminalNotFound(String),
+    #[error(
+        "could not start {1} selector '{0}' ({2}), install it or change \
+         `selector` in handlr.toml"
+    )]
+    SelectorUnavailable(String, &'static str, #[source] std::io::Error),
+    #[error(
+        "selector '{0}' needs a terminal, set `selector_is_terminal = false` \
+         for selectors with their own window like rofi or dmenu"
+    )]
+    SelectorNeedsTerminal(String),
+    #[error("no handler was selected")]
+    Cancelled,
+    #[error("no completions for shell '{0}'")]
+    UnknownShell(String),
+    #[error("unknown color choice '{0}', expected auto, always or never")]
+    UnknownColor(String),
+    #[error("editor exited with {0}")]
+    Editor(std::process::ExitStatus),
+    #[error("left {} unchanged, the edited copy was discarded", .0.display())]
+    EditDiscarded(std::path::PathBuf),
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -1,55 +1,331 @@
-use config::CONFIG;
+use config::{Config, CONFIG};
 use error::{Error, Result};
 use once_cell::sync::Lazy;
+use once_cell::unsync;
+use std::path::Path;
 
 mod apps;
 mod cli;
 mod common;
+mod completions;
 mod config;
 mod error;
 mod utils;
 
 fn main() -> Result<()> {
     use clap::Clap;
-    use cli::Cmd;
+    use cli::{Cli, Cmd, ConfigCmd};
+    use common::MimeOrExtension;
+    use std::sync::atomic::Ordering;
 
-    // create config if it doesn't exist
-    Lazy::force(&CONFIG);
+    let cli = Cli::parse();
+    apps::WRITE_DESKTOP.store(cli.desktop, Ordering::Relaxed);
+    apps::DRY_RUN.store(cli.dry_run, Ordering::Relaxed);
+    apps::VERBOSE.store(cli.verbose, Ordering::Relaxed);
+    utils::NULL_SEPARATED.store(cli.null, Ordering::Relaxed);
+    if let Some(path) = cli.config {
+        config::CONFIG_PATH.set(path).unwrap();
+    }
+    if let Some(path) = cli.mimeapps {
+        apps::MIMEAPPS_PATH.set(path).unwrap();
+    }
+    let (cmd, verbose) = (cli.cmd, cli.verbose);
+
+    // create config if it doesn't exist, unless we're only checking or
+    // fixing it
+    let config_loaded = !matches!(
+        cmd,
+        Cmd::CheckConfig
+            | Cmd::Config {
+                cmd: ConfigCmd::Edit
+            }
+    );
+    if config_loaded {
+        Lazy::force(&CONFIG);
+    }
 
-    let mut apps = (*apps::CANONICAL).clone();
+    // only read mimeapps.list once a command needs it
+    let mut apps = unsync::Lazy::new(|| (*apps::CANONICAL).clone());
 
     let res = || -> Result<()> {
-        match Cmd::parse() {
-            Cmd::Set { mime, handler } => {
-                apps.set_handler(mime.0, handler);
+        match cmd {
+            Cmd::Open {
+                paths,
+                with,
+                action,
+            } => {
+                apps.open(&paths, with.as_ref(), action.as_deref())?;
+            }
+            Cmd::Set {
+                mime,
+                handler,
+                mimes,
+                by_glob,
+            } => {
+                // bad mimes are skipped, failing only if none were good
+                let several = !mimes.is_empty();
+                let (mut set, mut first_error) = (0, None);
+                for mime in std::iter::once(mime).chain(mimes) {
+                    match MimeOrExtension::parse(&mime, by_glob) {
+                        Ok(mime) => {
+                            apps.set_handler(mime.0, handler.clone());
+                            set += 1;
+                        }
+                        Err(e) => {
+                            if several {
+                                eprintln!("skipping '{}': {}", mime, e);
+                            }
+                            first_error.get_or_insert(e);
+                        }
+                    }
+                }
+                match first_error {
+                    Some(e) if set == 0 => return Err(e),
+                    _ => apps.save()?,
+                }
+            }
+            Cmd::SetAll { handler } => {
+                let mut count = 0;
+                for mime in handler.get_entry()?.mime_types {
+                    match mime.parse::<mime::Mime>() {
+                        Ok(mime) => {
+                            apps.set_handler(mime, handler.clone());
+                            count += 1;
+                        }
+                        Err(e) => eprintln!("skipping '{}': {}", mime, e),
+                    }
+                }
                 apps.save()?;
+                println!("set {} as the default for {} mimes", handler, count);
             }
-            Cmd::Add { mime, handler } => {
-                apps.add_handler(mime.0, handler);
+            Cmd::Add {
+                mime,
+                handler,
+                first,
+                by_glob,
+            } => {
+                let mime = MimeOrExtension::parse(&mime, by_glob)?;
+                apps.add_handler(mime.0, handler, first);
                 apps.save()?;
             }
-            Cmd::List { all } => {
-                apps.print(all)?;
+            Cmd::List {
+                all,
+                raw,
+                json,
+                wide,
+                color,
+            } => {
+                if raw {
+                    apps.write_to(&mut std::io::stdout())?;
+                } else {
+                    apps.print(all, json, wide, color.enabled())?;
+                }
             }
             Cmd::Unset { mime } => {
                 apps.remove_handler(&mime.0)?;
             }
+            Cmd::UnsetAll { handler } => {
+                let affected = apps.unset_handler_everywhere(&handler);
+                if affected.is_empty() {
+                    println!("{} is not a default for any mime", handler);
+                } else {
+                    apps.save()?;
+                    for mime in affected {
+                        println!("unset {} for {}", handler, mime);
+                    }
+                }
+            }
+            Cmd::Block { mime, handler } => {
+                apps.block_handler(mime.0, handler);
+                apps.save()?;
+            }
+            Cmd::Remove { mime, handler } => {
+                apps.remove_association(mime.0, handler);
+                apps.save()?;
+            }
+            Cmd::Get { mime, json } => {
+                apps.show_handler(&mime.0, json, verbose)?;
+            }
+            Cmd::WhichHandles { handler, all } => {
+                let handled = apps.handled_by(&handler, all);
+                if handled.is_empty() {
+                    eprintln!("{} is not associated with any mime", handler);
+                }
+                for (section, mime, position) in handled {
+                    let role = match (section, position) {
+                        (apps::Section::Default, 0) => "default".to_owned(),
+                        (apps::Section::Default, _) => {
+                            format!("fallback #{}", position)
+                        }
+                        _ => format!("added #{}", position + 1),
+                    };
+                    utils::print_record(&[mime.to_string(), role]);
+                }
+            }
+            Cmd::Launch { mime, args, action } => {
+                apps.select_handler(&mime.0)?
+                    .launch(args, action.as_deref())?;
+            }
+            Cmd::Mime { paths, json } => {
+                let mut detected = Vec::with_capacity(paths.len());
+                for path in paths {
+                    match path.detect_mime() {
+                        Ok(mime) => detected.push(mime),
+                        Err(e) => eprintln!("skipping '{}': {}", path, e),
+                    }
+                }
+                if json {
+                    println!("{}", serde_json::to_string(&detected)?);
+                } else {
+                    for d in detected {
+                        // always three fields, so `-0` output stays parseable
+                        let guess = if d.guess { "guess" } else { "" };
+                        utils::print_record(&[&d.path, &d.mime, guess]);
+                    }
+                }
+            }
+            Cmd::Clean => {
+                let removed = apps.clean();
+                if !removed.is_empty() {
+                    apps.save()?;
+                }
+                for (section, mime, handler) in removed {
+                    println!(
+                        "removed {} for {} from [{}]",
+                        handler,
+                        mime,
+                        section.title()
+                    );
+                }
+            }
             Cmd::Fix => {
                 apps.save()?;
             }
+            Cmd::Diff { snapshot, json } => {
+                let old = apps::CanonicalMimeApps::from(
+                    apps::MimeApps::read_from(&snapshot)?,
+                );
+                let changes = apps.diff(&old);
+                if json {
+                    println!("{}", serde_json::to_string(&changes)?);
+                } else {
+                    let mut section = "";
+                    for change in changes {
+                        if change.section != section {
+                            section = change.section;
+                            println!("[{}]", section);
+                        }
+                        println!("{}", change);
+                    }
+                }
+            }
+            Cmd::Export { path } => {
+                let json = serde_json::to_string_pretty(&apps.effective()?)?;
+                match path {
+                    Some(path) => std::fs::write(path, json + "\n")?,
+                    None => println!("{}", json),
+                }
+            }
+            Cmd::Import { path, merge } => {
+                let handlers =
+                    serde_json::from_str(&std::fs::read_to_string(path)?)?;
+                for (mime, handler, e) in apps.import(handlers, merge) {
+                    eprintln!("skipping {} for '{}': {}", handler, mime, e);
+                }
+                apps.save()?;
+            }
+            Cmd::Validate { json } => {
+                let path = apps::MimeApps::path()?;
+                let problems = if path.exists() {
+                    apps::MimeApps::read_from(&path)?.validate()
+                } else {
+                    Vec::new()
+                };
+                if json {
+                    println!("{}", serde_json::to_string(&problems)?);
+                } else {
+                    let mut section = "";
+                    for problem in &problems {
+                        if problem.section != section {
+                            section = problem.section;
+                            println!("[{}]", section);
+                        }
+                        println!("{}", problem);
+                    }
+                }
+                if !problems.is_empty() {
+                    std::process::exit(1);
+                }
+            }
+            Cmd::Edit => {
+                let path = apps::MimeApps::path()?;
+                utils::edit_checked(&path, apps::MimeApps::check)?;
+            }
+            Cmd::CheckConfig => {
+                let checks = [
+                    (Config::path()?, Config::check as fn(&Path) -> _),
+                    (apps::MimeApps::path()?, apps::MimeApps::check),
+                ];
+                let mut failed = false;
+                for (path, check) in checks {
+                    match check(&path) {
+                        Ok(()) => println!("{}: OK", path.display()),
+                        Err(e) => {
+                            failed = true;
+                            println!("{}: {}", path.display(), e);
+                        }
+                    }
+                }
+                if failed {
+                    std::process::exit(1);
+                }
+            }
+            Cmd::Config {
+                cmd: ConfigCmd::Dump,
+            } => {
+                print!("{}", CONFIG.dump()?);
+            }
+            Cmd::Config {
+                cmd: ConfigCmd::Edit,
+            } => {
+                utils::edit_checked(&Config::path()?, Config::check)?;
+            }
+            Cmd::Completions { shell } => {
+                print!("{}", shell.script());
+            }
+            Cmd::Autocomplete {
+                desktop_files,
+                mimes,
+            } => {
+                if desktop_files {
+                    for entry in common::DesktopEntry::installed()? {
+                        utils::print_record(&[entry.file_name(), entry.name]);
+                    }
+                }
+                if mimes {
+                    for mime in apps.mimes() {
+                        utils::print_record(&[mime.to_string()]);
+                    }
+                }
+            }
         }
         Ok(())
     }();
 
-    match (res, atty::is(atty::Stream::Stdout)) {
-        (Err(e), true) => {
-            eprintln!("{}", e);
-            std::process::exit(1);
-        }
-        (Err(e), false) => {
-            utils::notify("handlr error", &e.to_string())?;
-            std::process::exit(1);
+    if let Err(e) = res {
+        let tty = atty::is(atty::Stream::Stdout);
+        eprintln!("{}", e);
+        let notify = if config_loaded {
+            CONFIG.notifies_errors(tty)
+        } else {
+            !tty
+        };
+        // the error was already printed, so a missing notify-send isn't
+        // worth a second one
+        if notify {
+            utils::notify("handlr error", &e.to_string()).ok();
         }
-        _ => Ok(()),
+        std::process::exit(1);
     }
+    Ok(())
 }
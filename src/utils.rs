@@ -1,7 +1,206 @@
-use crate::Result;
+use crate::common::atomic_save::{AllowOverwrite, AtomicFile, Durability};
+use crate::{Error, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by `-0/--null`, to end every printed field with a NUL byte
+pub static NULL_SEPARATED: AtomicBool = AtomicBool::new(false);
+
+/// Held by tests that set environment variables other tests read
+#[cfg(test)]
+pub static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
 pub fn notify(title: &str, msg: &str) -> Result<()> {
     std::process::Command::new("notify-send")
-        .args(&["-t", "10000", title, msg])
+        .args(["-t", "10000", title, msg])
         .spawn()?;
     Ok(())
 }
+
+/// Print one record of a listing: tab-separated fields on a line, or with
+/// `--null` each field ended by a NUL byte, safe for any file name
+pub fn print_record<S: AsRef<str>>(fields: &[S]) {
+    print!(
+        "{}",
+        format_record(fields, NULL_SEPARATED.load(Ordering::Relaxed))
+    );
+}
+
+fn format_record<S: AsRef<str>>(fields: &[S], null: bool) -> String {
+    if null {
+        fields.iter().map(|f| format!("{}\0", f.as_ref())).collect()
+    } else {
+        let fields = fields.iter().map(AsRef::as_ref).collect::<Vec<_>>();
+        format!("{}\n", fields.join("\t"))
+    }
+}
+
+/// Open `path` in $VISUAL/$EDITOR (falling back to vi) and wait for it to exit
+pub fn edit(path: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".into());
+    let mut split = shlex::split(&editor)
+        .filter(|split| !split.is_empty())
+        .ok_or_else(|| Error::BadCmd(editor.clone()))?;
+
+    let status = Command::new(split.remove(0))
+        .args(split)
+        .arg(path)
+        .status()?;
+    if !status.success() {
+        return Err(Error::Editor(status));
+    }
+    Ok(())
+}
+
+/// Edit a copy of `path` and only write it back once `check` accepts it,
+/// offering to reopen the editor until it does. The contents are saved
+/// atomically to the file a symlink points at, keeping the link and the
+/// file's mode.
+pub fn edit_checked(
+    path: &Path,
+    check: impl Fn(&Path) -> Result<()>,
+) -> Result<()> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    std::fs::create_dir_all(dir)?;
+    // keep the file name at the end so editors still pick the right syntax
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let copy = tempfile::Builder::new()
+        .prefix(".handlr-edit-")
+        .suffix(&format!("-{}", name))
+        .tempfile_in(dir)?;
+    if path.exists() {
+        std::fs::copy(path, copy.path())?;
+    }
+
+    loop {
+        edit(copy.path())?;
+        match check(copy.path()) {
+            Ok(()) => break,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                if !confirm("Reopen the editor?")? {
+                    return Err(Error::EditDiscarded(path.to_owned()));
+                }
+            }
+        }
+    }
+
+    let target = std::fs::canonicalize(path).unwrap_or_else(|_| path.into());
+    let permissions = std::fs::metadata(&target).ok().map(|m| m.permissions());
+    let contents = std::fs::read(copy.path())?;
+    AtomicFile::new(&target, AllowOverwrite, Durability::SyncDir)
+        .write(|f| {
+            f.write_all(&contents)?;
+            match permissions {
+                Some(permissions) => f.set_permissions(permissions),
+                None => Ok(()),
+            }
+        })
+        .map_err(std::io::Error::from)?;
+    Ok(())
+}
+
+/// Ask a yes/no question on the terminal, defaulting to no
+pub fn confirm(question: &str) -> Result<bool> {
+    eprint!("{} [y/N] ", question);
+    std::io::stderr().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// The candidate fewest edits away from `target`, if any is within two,
+/// preferring the first on ties
+pub fn closest<'a>(
+    target: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(target, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance, counting inserted, deleted and replaced chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Find an executable like the shell would, searching $PATH unless `bin`
+/// already contains a slash
+pub fn find_in_path(bin: &str) -> Option<PathBuf> {
+    if bin.contains('/') {
+        return Some(PathBuf::from(bin)).filter(|path| path.is_file());
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(bin))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records() {
+        let fields = ["my file.txt", "text/plain"];
+        assert_eq!(format_record(&fields, false), "my file.txt\ttext/plain\n");
+        assert_eq!(format_record(&fields, true), "my file.txt\0text/plain\0");
+    }
+
+    #[test]
+    fn suggestions() {
+        let known = ["text/html", "text/plain", "image/png"];
+        assert_eq!(edit_distance("text/htm", "text/html"), 1);
+        assert_eq!(edit_distance("image/pgn", "image/png"), 2);
+        assert_eq!(closest("text/htm", known), Some("text/html"));
+        assert_eq!(closest("txt/plain", known), Some("text/plain"));
+        assert_eq!(closest("video/mp4", known), None);
+    }
+
+    #[test]
+    fn edit_checked_writes_copy_back() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir()?;
+        let target = dir.path().join("dotfiles-mimeapps.list");
+        std::fs::write(&target, "old\n")?;
+        std::fs::set_permissions(
+            &target,
+            std::fs::Permissions::from_mode(0o600),
+        )?;
+        let path = dir.path().join("mimeapps.list");
+        std::os::unix::fs::symlink(&target, &path)?;
+        std::env::set_var("VISUAL", "sh -c 'echo new > \"$0\"'");
+
+        edit_checked(&path, |copy| {
+            assert_ne!(copy, path);
+            Ok(())
+        })?;
+        assert_eq!(std::fs::read_to_string(&target)?, "new\n");
+        assert!(std::fs::symlink_metadata(&path)?.file_type().is_symlink());
+        let mode = std::fs::metadata(&target)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // the copy is cleaned up
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 2);
+        Ok(())
+    }
+}